@@ -4,22 +4,18 @@ use vtmachine::VtMachine;
 
 fn main() -> Result<(), std::io::Error> {
     let mut machine = VtMachine::new();
-    let mut char_stream = u8char::stream::U8CharStream::new();
 
     let mut r = stdin();
     let mut buf = [0_u8; 64];
     loop {
-        // VtMachine wants u8char as input, so we need to interpret stdin as
-        // UTF-8 first before we can feed chunks to the state machine.
+        // VtMachine can interpret stdin as UTF-8 itself, so we can pass each
+        // chunk directly to the state machine regardless of whether it ends
+        // partway through a character.
         let read_len = r.read(&mut buf[..])?;
         if read_len == 0 {
+            machine.write_bytes_end(|event| println!("{event:?}"));
             return Ok(());
         }
-        let buf = &buf[..read_len];
-        for c in char_stream.more(buf) {
-            for event in machine.write_u8char(c) {
-                println!("{event:?}");
-            }
-        }
+        machine.write_bytes(&buf[..read_len], |event| println!("{event:?}"));
     }
 }
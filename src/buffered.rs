@@ -0,0 +1,289 @@
+use alloc::vec::Vec;
+
+use u8char::u8char;
+
+use crate::{DcsCommand, VtEvent, VtMachine, VtParams};
+
+/// Wraps a [`VtMachine`] to deliver the content of operating system commands
+/// and device control strings as a single event each, rather than as one
+/// event per character.
+///
+/// The string content is accumulated in a heap-allocated buffer whose size is
+/// limited by [`VtBufferedMachine::with_max_len`], so that a hostile or buggy
+/// program cannot exhaust memory by writing an unterminated string. Any
+/// content beyond that limit is discarded and the resulting event is reported
+/// as truncated.
+///
+/// All other events from the wrapped machine are passed through as
+/// [`VtBufferedEvent::Event`].
+///
+/// This type is available only when the `alloc` feature is enabled.
+pub struct VtBufferedMachine<const PARAMS: usize = 16, const INTERMEDIATES: usize = 2> {
+    machine: VtMachine<PARAMS, INTERMEDIATES>,
+    strings: StringBuffer,
+}
+
+impl<const PARAMS: usize, const INTERMEDIATES: usize> VtBufferedMachine<PARAMS, INTERMEDIATES> {
+    /// The default maximum length of a buffered string, in bytes.
+    pub const DEFAULT_MAX_LEN: usize = 1 << 20;
+
+    /// Wraps the given machine.
+    pub fn new(machine: VtMachine<PARAMS, INTERMEDIATES>) -> Self {
+        Self {
+            machine,
+            strings: StringBuffer {
+                buf: Vec::new(),
+                max_len: Self::DEFAULT_MAX_LEN,
+                truncated: false,
+                current: None,
+                dcs_params: Vec::new(),
+                dcs_flags: Vec::new(),
+                dcs_intermediates: Vec::new(),
+            },
+        }
+    }
+
+    /// Returns the buffered machine with a different limit on the number of
+    /// bytes of string content it will retain for each string.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.strings.max_len = max_len;
+        self
+    }
+
+    /// Consumes a single unicode scalar value given as a [`u8char`], passing
+    /// each of the events it causes to the given callback.
+    pub fn write_u8char(&mut self, c: u8char, mut f: impl FnMut(VtBufferedEvent<'_>)) {
+        for event in self.machine.write_u8char(c) {
+            self.strings.handle(event, &mut f);
+        }
+    }
+
+    /// Consumes a chunk of UTF-8 bytes, passing each of the events they cause
+    /// to the given callback.
+    ///
+    /// This has the same treatment of character boundaries and invalid
+    /// sequences as [`VtMachine::write_bytes`].
+    pub fn write_bytes(&mut self, bytes: &[u8], mut f: impl FnMut(VtBufferedEvent<'_>)) {
+        let strings = &mut self.strings;
+        self.machine
+            .write_bytes(bytes, |event| strings.handle(event, &mut f));
+    }
+
+    /// Tells the machine that no more bytes are expected, passing any final
+    /// events to the given callback.
+    ///
+    /// This is the equivalent of [`VtMachine::write_bytes_end`], and so a
+    /// string that is still open is delivered with the terminator
+    /// [`VtEvent::END_OF_STREAM`].
+    pub fn write_bytes_end(&mut self, mut f: impl FnMut(VtBufferedEvent<'_>)) {
+        let strings = &mut self.strings;
+        self.machine
+            .write_bytes_end(|event| strings.handle(event, &mut f));
+    }
+
+    /// Tells the machine that no more characters are expected, passing any
+    /// final events to the given callback.
+    ///
+    /// This is the equivalent of [`VtMachine::write_end`], for callers that
+    /// use [`Self::write_u8char`] rather than [`Self::write_bytes`].
+    pub fn write_end(&mut self, mut f: impl FnMut(VtBufferedEvent<'_>)) {
+        for event in self.machine.write_end() {
+            self.strings.handle(event, &mut f);
+        }
+    }
+
+    /// Returns a reference to the wrapped machine.
+    pub fn machine(&self) -> &VtMachine<PARAMS, INTERMEDIATES> {
+        &self.machine
+    }
+}
+
+/// An event from [`VtBufferedMachine`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtBufferedEvent<'m> {
+    /// An event from the wrapped [`VtMachine`] that is not part of an
+    /// operating system command or device control string.
+    Event(VtEvent<'m>),
+    /// A complete operating system command.
+    Osc {
+        /// The content of the command, split at each semicolon.
+        params: &'m [&'m [u8]],
+        /// The complete content of the command, which can be decoded using
+        /// [`crate::OscCommand::parse`].
+        data: &'m [u8],
+        /// The character that ended the command, as would be reported by
+        /// [`VtEvent::OscEnd`].
+        terminator: u8,
+        /// Set if the content was longer than the maximum length, in which
+        /// case `params` and `data` include only the content up to that
+        /// limit.
+        truncated: bool,
+    },
+    /// A complete device control string.
+    Dcs {
+        /// The information that would be reported by [`VtEvent::DcsStart`].
+        header: VtDcsHeader<'m>,
+        /// The content of the data string.
+        data: &'m [u8],
+        /// The character that ended the string, as would be reported by
+        /// [`VtEvent::DcsEnd`].
+        terminator: u8,
+        /// Set if the data string was longer than the maximum length, in which
+        /// case `data` includes only the content up to that limit.
+        truncated: bool,
+    },
+}
+
+impl<'m> VtBufferedEvent<'m> {
+    /// Decodes the event as a [`DcsCommand`] if it is a
+    /// [`VtBufferedEvent::Dcs`], or returns `None` for any other event.
+    pub fn dcs_command(&self) -> Option<DcsCommand<'m>> {
+        match *self {
+            VtBufferedEvent::Dcs { header, data, .. } => Some(DcsCommand::new(
+                header.cmd,
+                header.private_marker,
+                header.params,
+                header.intermediates,
+                data,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// The header of a device control string, as reported in
+/// [`VtBufferedEvent::Dcs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VtDcsHeader<'m> {
+    /// The final character of the header.
+    pub cmd: u8,
+    /// The private-use marker character, if any.
+    pub private_marker: Option<u8>,
+    /// The parameters given in the header.
+    pub params: VtParams<'m>,
+    /// Any intermediate characters that appeared in the header.
+    pub intermediates: &'m [u8],
+    /// Set if the header had more parameters than the [`VtMachine`] has
+    /// capacity for.
+    pub truncated: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StringKind {
+    Osc,
+    Dcs {
+        cmd: u8,
+        private_marker: Option<u8>,
+        truncated: bool,
+    },
+}
+
+/// The state of [`VtBufferedMachine`] other than the wrapped machine itself,
+/// kept separate so that it can be borrowed while the machine is also
+/// borrowed.
+struct StringBuffer {
+    buf: Vec<u8>,
+    max_len: usize,
+    truncated: bool,
+    current: Option<StringKind>,
+    dcs_params: Vec<u16>,
+    dcs_flags: Vec<u8>,
+    dcs_intermediates: Vec<u8>,
+}
+
+impl StringBuffer {
+    fn handle(&mut self, event: VtEvent<'_>, f: &mut impl FnMut(VtBufferedEvent<'_>)) {
+        match event {
+            VtEvent::OscStart(_) => self.begin(StringKind::Osc),
+            VtEvent::DcsStart {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            } => {
+                // The machine will have discarded the header by the time the
+                // string ends, so we need our own copy of it.
+                self.dcs_params.clear();
+                self.dcs_params.extend_from_slice(params.values());
+                self.dcs_flags.clear();
+                self.dcs_flags
+                    .extend((0..params.values().len()).map(|idx| params.flag(idx)));
+                self.dcs_intermediates.clear();
+                self.dcs_intermediates.extend_from_slice(intermediates);
+                self.begin(StringKind::Dcs {
+                    cmd,
+                    private_marker,
+                    truncated,
+                });
+            }
+            VtEvent::OscChar(c) | VtEvent::DcsChar(c) => self.push(c),
+            VtEvent::OscEnd(terminator) => {
+                let params: Vec<&[u8]> = self.buf.split(|b| *b == b';').collect();
+                f(VtBufferedEvent::Osc {
+                    params: &params,
+                    data: &self.buf,
+                    terminator,
+                    truncated: self.truncated,
+                });
+                self.end();
+            }
+            VtEvent::DcsEnd(terminator) => {
+                if let Some(StringKind::Dcs {
+                    cmd,
+                    private_marker,
+                    truncated,
+                }) = self.current
+                {
+                    let header = VtDcsHeader {
+                        cmd,
+                        private_marker,
+                        params: VtParams::from_parts(&self.dcs_params, &self.dcs_flags),
+                        intermediates: &self.dcs_intermediates,
+                        truncated,
+                    };
+                    f(VtBufferedEvent::Dcs {
+                        header,
+                        data: &self.buf,
+                        terminator,
+                        truncated: self.truncated,
+                    });
+                }
+                self.end();
+            }
+            event => f(VtBufferedEvent::Event(event)),
+        }
+    }
+
+    fn begin(&mut self, kind: StringKind) {
+        self.buf.clear();
+        self.truncated = false;
+        self.current = Some(kind);
+    }
+
+    fn push(&mut self, c: u8char) {
+        // Once anything has been discarded, the buffered content must stop
+        // there so that it remains a prefix of the real string.
+        if self.truncated {
+            return;
+        }
+        let mut encoded = [0_u8; 4];
+        let encoded = c.to_char().encode_utf8(&mut encoded).as_bytes();
+        if self.buf.len() + encoded.len() > self.max_len {
+            self.truncated = true;
+            return;
+        }
+        self.buf.extend_from_slice(encoded);
+    }
+
+    fn end(&mut self) {
+        self.current = None;
+        self.buf.clear();
+        // Don't keep holding on to an unusually-large allocation after the
+        // string that needed it has ended.
+        if self.buf.capacity() > 4096 {
+            self.buf.shrink_to(4096);
+        }
+    }
+}
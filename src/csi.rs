@@ -0,0 +1,236 @@
+use crate::{VtEvent, VtParams};
+
+/// A control sequence decoded into one of the commands commonly supported by
+/// ECMA-48 and xterm-compatible terminals.
+///
+/// Use [`CsiCommand::new`] with the fields of a [`VtEvent::DispatchCsi`], or
+/// [`VtEvent::csi_command`] with the event itself.
+///
+/// Omitted parameters are replaced with the defaults specified for each
+/// command. Following common terminal behavior, a count or position of zero
+/// is treated the same as one. Sequences that this decoder does not
+/// recognize, including those with unexpected private-use markers or
+/// intermediates, are reported as [`CsiCommand::Unknown`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsiCommand<'m> {
+    /// CUU: move the cursor up by the given number of lines.
+    CursorUp(u16),
+    /// CUD: move the cursor down by the given number of lines.
+    CursorDown(u16),
+    /// CUF: move the cursor right by the given number of columns.
+    CursorForward(u16),
+    /// CUB: move the cursor left by the given number of columns.
+    CursorBack(u16),
+    /// CNL: move the cursor to the start of the line the given number of
+    /// lines down.
+    CursorNextLine(u16),
+    /// CPL: move the cursor to the start of the line the given number of
+    /// lines up.
+    CursorPrevLine(u16),
+    /// CHA or HPA: move the cursor to the given column, counting from one.
+    CursorColumn(u16),
+    /// VPA: move the cursor to the given row, counting from one.
+    CursorRow(u16),
+    /// CUP or HVP: move the cursor to the given row and column, counting
+    /// from one.
+    CursorPosition { row: u16, col: u16 },
+    /// CHT: move the cursor forward by the given number of tab stops.
+    CursorForwardTab(u16),
+    /// CBT: move the cursor back by the given number of tab stops.
+    CursorBackTab(u16),
+    /// ED: erase some or all of the display.
+    EraseInDisplay(EraseMode),
+    /// EL: erase some or all of the current line.
+    EraseInLine(EraseMode),
+    /// ICH: insert the given number of blank characters at the cursor.
+    InsertChars(u16),
+    /// DCH: delete the given number of characters at the cursor.
+    DeleteChars(u16),
+    /// ECH: erase the given number of characters starting at the cursor.
+    EraseChars(u16),
+    /// IL: insert the given number of blank lines at the cursor.
+    InsertLines(u16),
+    /// DL: delete the given number of lines at the cursor.
+    DeleteLines(u16),
+    /// SU: scroll the content up by the given number of lines.
+    ScrollUp(u16),
+    /// SD: scroll the content down by the given number of lines.
+    ScrollDown(u16),
+    /// REP: repeat the preceding graphic character the given number of times.
+    RepeatChar(u16),
+    /// DA: request the terminal's primary device attributes.
+    DeviceAttributes,
+    /// Secondary DA: request the terminal's type and version.
+    SecondaryDeviceAttributes,
+    /// SM: enable each of the given ANSI modes.
+    SetMode(VtParams<'m>),
+    /// RM: disable each of the given ANSI modes.
+    ResetMode(VtParams<'m>),
+    /// DECSET: enable each of the given DEC private modes.
+    DecSet(VtParams<'m>),
+    /// DECRST: disable each of the given DEC private modes.
+    DecReset(VtParams<'m>),
+    /// SGR: change the rendition of subsequent characters, as described by
+    /// the given parameters.
+    SelectGraphicRendition(VtParams<'m>),
+    /// DSR: request a status report of the given kind, such as 6 for the
+    /// cursor position.
+    DeviceStatusReport(u16),
+    /// DECSTBM: set the top and bottom margins of the scrolling region,
+    /// counting from one. A bottom of `None` means the last line of the
+    /// display.
+    SetScrollingRegion { top: u16, bottom: Option<u16> },
+    /// SCOSC: save the cursor position.
+    SaveCursor,
+    /// SCORC: restore the cursor position saved by [`Self::SaveCursor`].
+    RestoreCursor,
+    /// DECSCUSR: set the cursor style, where zero means the terminal's
+    /// default.
+    SetCursorStyle(u16),
+    /// DECSTR: perform a soft terminal reset.
+    SoftReset,
+    /// Any control sequence not covered by the other variants, or one that
+    /// had more parameters than the machine has capacity for.
+    Unknown {
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParams<'m>,
+        intermediates: &'m [u8],
+    },
+}
+
+impl<'m> CsiCommand<'m> {
+    /// Decodes the given fields of a [`VtEvent::DispatchCsi`].
+    ///
+    /// This does not know whether the parameters were truncated, so check
+    /// `truncated` first or use [`VtEvent::csi_command`], which does.
+    pub fn new(
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParams<'m>,
+        intermediates: &'m [u8],
+    ) -> Self {
+        let count = || params.param_or(0, 1).max(1);
+        let unknown = CsiCommand::Unknown {
+            cmd,
+            private_marker,
+            params,
+            intermediates,
+        };
+        match (private_marker, intermediates, cmd) {
+            (None, [], b'A') => CsiCommand::CursorUp(count()),
+            (None, [], b'B') => CsiCommand::CursorDown(count()),
+            (None, [], b'C') => CsiCommand::CursorForward(count()),
+            (None, [], b'D') => CsiCommand::CursorBack(count()),
+            (None, [], b'E') => CsiCommand::CursorNextLine(count()),
+            (None, [], b'F') => CsiCommand::CursorPrevLine(count()),
+            (None, [], b'G' | b'`') => CsiCommand::CursorColumn(count()),
+            (None, [], b'd') => CsiCommand::CursorRow(count()),
+            (None, [], b'H' | b'f') => CsiCommand::CursorPosition {
+                row: params.param_or(0, 1).max(1),
+                col: params.param_or(1, 1).max(1),
+            },
+            (None, [], b'I') => CsiCommand::CursorForwardTab(count()),
+            (None, [], b'Z') => CsiCommand::CursorBackTab(count()),
+            (None, [], b'J') => match EraseMode::from_param(params.param_or(0, 0)) {
+                Some(mode) => CsiCommand::EraseInDisplay(mode),
+                None => unknown,
+            },
+            (None, [], b'K') => match EraseMode::from_param(params.param_or(0, 0)) {
+                Some(EraseMode::Scrollback) | None => unknown,
+                Some(mode) => CsiCommand::EraseInLine(mode),
+            },
+            (None, [], b'@') => CsiCommand::InsertChars(count()),
+            (None, [], b'P') => CsiCommand::DeleteChars(count()),
+            (None, [], b'X') => CsiCommand::EraseChars(count()),
+            (None, [], b'L') => CsiCommand::InsertLines(count()),
+            (None, [], b'M') => CsiCommand::DeleteLines(count()),
+            (None, [], b'S') => CsiCommand::ScrollUp(count()),
+            (None, [], b'T') => CsiCommand::ScrollDown(count()),
+            (None, [], b'b') => CsiCommand::RepeatChar(count()),
+            (None, [], b'c') if params.param_or(0, 0) == 0 => CsiCommand::DeviceAttributes,
+            (Some(b'>'), [], b'c') if params.param_or(0, 0) == 0 => {
+                CsiCommand::SecondaryDeviceAttributes
+            }
+            (None, [], b'h') => CsiCommand::SetMode(params),
+            (None, [], b'l') => CsiCommand::ResetMode(params),
+            (Some(b'?'), [], b'h') => CsiCommand::DecSet(params),
+            (Some(b'?'), [], b'l') => CsiCommand::DecReset(params),
+            (None, [], b'm') => CsiCommand::SelectGraphicRendition(params),
+            (None, [], b'n') => match params.get(0) {
+                Some(kind) => CsiCommand::DeviceStatusReport(kind),
+                None => unknown,
+            },
+            (None, [], b'r') => CsiCommand::SetScrollingRegion {
+                top: params.param_or(0, 1).max(1),
+                bottom: params.get(1).filter(|v| *v != 0),
+            },
+            (None, [], b's') if params.is_empty() => CsiCommand::SaveCursor,
+            (None, [], b'u') if params.is_empty() => CsiCommand::RestoreCursor,
+            (None, [b' '], b'q') => CsiCommand::SetCursorStyle(params.param_or(0, 0)),
+            (None, [b'!'], b'p') if params.is_empty() => CsiCommand::SoftReset,
+            _ => unknown,
+        }
+    }
+}
+
+/// Which part of the display or line to erase, as used by
+/// [`CsiCommand::EraseInDisplay`] and [`CsiCommand::EraseInLine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EraseMode {
+    /// Erase from the cursor to the end of the display or line.
+    ToEnd,
+    /// Erase from the start of the display or line to the cursor.
+    ToStart,
+    /// Erase the entire display or line.
+    All,
+    /// Erase the scrollback buffer, which applies only to
+    /// [`CsiCommand::EraseInDisplay`].
+    Scrollback,
+}
+
+impl EraseMode {
+    fn from_param(v: u16) -> Option<Self> {
+        match v {
+            0 => Some(EraseMode::ToEnd),
+            1 => Some(EraseMode::ToStart),
+            2 => Some(EraseMode::All),
+            3 => Some(EraseMode::Scrollback),
+            _ => None,
+        }
+    }
+}
+
+impl<'m> VtEvent<'m> {
+    /// Decodes the event as a [`CsiCommand`] if it is a
+    /// [`VtEvent::DispatchCsi`], or returns `None` for any other event.
+    ///
+    /// A sequence whose parameters were truncated is always reported as
+    /// [`CsiCommand::Unknown`], because decoding only the parameters that
+    /// were kept could silently change its meaning.
+    pub fn csi_command(&self) -> Option<CsiCommand<'m>> {
+        match *self {
+            VtEvent::DispatchCsi {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated: true,
+            } => Some(CsiCommand::Unknown {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+            }),
+            VtEvent::DispatchCsi {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated: false,
+            } => Some(CsiCommand::new(cmd, private_marker, params, intermediates)),
+            _ => None,
+        }
+    }
+}
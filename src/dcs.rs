@@ -0,0 +1,176 @@
+use crate::VtParams;
+
+/// A complete device control string decoded into one of the requests
+/// commonly supported by xterm-compatible terminals.
+///
+/// Use [`DcsCommand::new`] with the fields of a [`crate::VtEvent::DcsStart`]
+/// and the characters gathered from the [`crate::VtEvent::DcsChar`] events
+/// that follow it, or with the fields of a `VtBufferedEvent::Dcs`.
+/// Strings that this decoder does not recognize, or whose content is not in
+/// the expected form, are reported as [`DcsCommand::Unknown`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DcsCommand<'m> {
+    /// DECRQSS, `DCS $ q ... ST`: request the current value of the setting
+    /// identified by the given characters, such as `m` for SGR or `r` for
+    /// DECSTBM.
+    RequestStatus(&'m [u8]),
+    /// XTGETTCAP, `DCS + q ... ST`: request the values of one or more
+    /// termcap or terminfo capabilities, whose names are hex-encoded.
+    RequestTermcap(HexList<'m>),
+    /// DECUDK, `DCS ... | ... ST`: define the strings sent by one or more
+    /// programmable function keys.
+    UserDefinedKeys {
+        /// Set if all existing definitions should be cleared first, rather
+        /// than only replacing the ones being defined.
+        clear: bool,
+        /// Set if the definitions should be locked against further changes.
+        lock: bool,
+        /// The key selectors and their hex-encoded strings.
+        keys: UdkDefinitions<'m>,
+    },
+    /// Any device control string not covered by the other variants.
+    Unknown {
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParams<'m>,
+        intermediates: &'m [u8],
+        data: &'m [u8],
+    },
+}
+
+impl<'m> DcsCommand<'m> {
+    /// Decodes a device control string with the given header and content.
+    pub fn new(
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParams<'m>,
+        intermediates: &'m [u8],
+        data: &'m [u8],
+    ) -> Self {
+        // An empty string has no items at all, rather than one empty item.
+        let rest = (!data.is_empty()).then_some(data);
+        match (private_marker, intermediates, cmd) {
+            (None, [b'$'], b'q') if params.is_empty() => DcsCommand::RequestStatus(data),
+            (None, [b'+'], b'q') if params.is_empty() => {
+                DcsCommand::RequestTermcap(HexList { rest })
+            }
+            (None, [], b'|') => DcsCommand::UserDefinedKeys {
+                clear: params.param_or(0, 0) != 1,
+                lock: params.param_or(1, 0) != 1,
+                keys: UdkDefinitions { rest },
+            },
+            _ => DcsCommand::Unknown {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                data,
+            },
+        }
+    }
+}
+
+/// Iterator over the semicolon-separated hex-encoded strings in a
+/// [`DcsCommand::RequestTermcap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexList<'m> {
+    rest: Option<&'m [u8]>,
+}
+
+impl<'m> Iterator for HexList<'m> {
+    type Item = HexData<'m>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (item, rest) = split_at_byte(self.rest.take()?, b';');
+        self.rest = rest;
+        Some(HexData(item))
+    }
+}
+
+/// Iterator over the key selectors and hex-encoded strings in a
+/// [`DcsCommand::UserDefinedKeys`].
+///
+/// The iterator ends early if it encounters a definition that is not
+/// valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdkDefinitions<'m> {
+    rest: Option<&'m [u8]>,
+}
+
+impl<'m> Iterator for UdkDefinitions<'m> {
+    type Item = (u16, HexData<'m>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (item, rest) = split_at_byte(self.rest.take()?, b';');
+        let (key, value) = split_at_byte(item, b'/');
+        let key = core::str::from_utf8(key).ok()?.parse().ok()?;
+        let value = HexData(value?);
+        self.rest = rest;
+        Some((key, value))
+    }
+}
+
+impl<'m> core::iter::FusedIterator for UdkDefinitions<'m> {}
+
+/// Hex-encoded data, which can be decoded without allocating by iterating
+/// over [`HexData::bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexData<'m>(&'m [u8]);
+
+impl<'m> HexData<'m> {
+    /// Returns the data in its original encoded form.
+    pub fn encoded(&self) -> &'m [u8] {
+        self.0
+    }
+
+    /// Returns true if the data consists entirely of pairs of hex digits.
+    pub fn is_valid(&self) -> bool {
+        // Decoding stops early at anything invalid, including an unpaired
+        // digit at the end.
+        self.bytes().count() * 2 == self.0.len()
+    }
+
+    /// Returns an iterator over the decoded bytes.
+    ///
+    /// Decoding stops at the first invalid or unpaired digit, so use
+    /// [`Self::is_valid`] first to reject malformed data.
+    pub fn bytes(&self) -> HexBytes<'m> {
+        HexBytes { input: self.0 }
+    }
+
+    /// Returns true if the data is valid and decodes to exactly the given
+    /// bytes, such as a capability name in [`DcsCommand::RequestTermcap`].
+    pub fn decodes_to(&self, want: &[u8]) -> bool {
+        self.is_valid() && self.0.len() == want.len() * 2 && self.bytes().eq(want.iter().copied())
+    }
+}
+
+/// Iterator over the decoded bytes of a [`HexData`].
+#[derive(Debug, Clone)]
+pub struct HexBytes<'m> {
+    input: &'m [u8],
+}
+
+impl<'m> Iterator for HexBytes<'m> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let [high, low, rest @ ..] = self.input else {
+            return None;
+        };
+        let high = (*high as char).to_digit(16)?;
+        let low = (*low as char).to_digit(16)?;
+        self.input = rest;
+        Some(((high << 4) | low) as u8)
+    }
+}
+
+/// Splits the given data at the first instance of the given byte, returning
+/// `None` as the second element if there isn't one.
+fn split_at_byte(data: &[u8], sep: u8) -> (&[u8], Option<&[u8]>) {
+    match data.iter().position(|b| *b == sep) {
+        Some(idx) => (&data[..idx], Some(&data[idx + 1..])),
+        None => (data, None),
+    }
+}
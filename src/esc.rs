@@ -0,0 +1,151 @@
+use crate::VtEvent;
+
+/// An escape sequence decoded into one of the commands commonly supported by
+/// VT100-compatible terminals.
+///
+/// Use [`EscCommand::new`] with the fields of a [`VtEvent::DispatchEsc`], or
+/// [`VtEvent::esc_command`] with the event itself. Sequences that this
+/// decoder does not recognize are reported as [`EscCommand::Unknown`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscCommand<'m> {
+    /// DECSC, `ESC 7`: save the cursor position and rendition.
+    SaveCursor,
+    /// DECRC, `ESC 8`: restore the state saved by [`Self::SaveCursor`].
+    RestoreCursor,
+    /// RIS, `ESC c`: reset the terminal to its initial state.
+    FullReset,
+    /// IND, `ESC D`: move the cursor down one line, scrolling if needed.
+    Index,
+    /// NEL, `ESC E`: move the cursor to the start of the next line,
+    /// scrolling if needed.
+    NextLine,
+    /// RI, `ESC M`: move the cursor up one line, scrolling if needed.
+    ReverseIndex,
+    /// HTS, `ESC H`: set a tab stop at the cursor's column.
+    TabSet,
+    /// SS2, `ESC N`: use the G2 character set for the next character only.
+    SingleShift2,
+    /// SS3, `ESC O`: use the G3 character set for the next character only.
+    SingleShift3,
+    /// DECKPAM, `ESC =`: switch the keypad to application mode.
+    KeypadApplicationMode,
+    /// DECKPNM, `ESC >`: switch the keypad to numeric mode.
+    KeypadNumericMode,
+    /// DECALN, `ESC # 8`: fill the screen with `E` characters.
+    AlignmentTest,
+    /// SCS, such as `ESC ( B`: designate the character set identified by
+    /// `charset` to the given slot.
+    ///
+    /// This crate does not implement any legacy character sets itself, but
+    /// reports these so that callers can either implement them or ignore
+    /// them deliberately.
+    ///
+    /// Some character sets are identified by an intermediate character
+    /// followed by the final character, such as `ESC ( % 5` for DEC
+    /// Supplemental Graphic, which requires the machine to have capacity for
+    /// at least two intermediates. An identifier with more than one
+    /// intermediate character is reported as [`Self::Unknown`].
+    DesignateCharset {
+        /// The slot being assigned to.
+        slot: CharsetSlot,
+        /// The size of the character set, which is implied by the
+        /// designator and so is part of the set's identity: `ESC ) A` is
+        /// the 94-character United Kingdom set, while `ESC - A` is the
+        /// 96-character ISO Latin-1 supplement.
+        size: CharsetSize,
+        /// The intermediate character that begins the identifier, if any,
+        /// such as `%` in `ESC ( % 5`.
+        charset_intermediate: Option<u8>,
+        /// The final character of the identifier.
+        charset: u8,
+    },
+    /// Any escape sequence not covered by the other variants.
+    Unknown { cmd: u8, intermediates: &'m [u8] },
+}
+
+impl<'m> EscCommand<'m> {
+    /// Decodes the given fields of a [`VtEvent::DispatchEsc`].
+    pub fn new(cmd: u8, intermediates: &'m [u8]) -> Self {
+        match (intermediates, cmd) {
+            ([], b'7') => EscCommand::SaveCursor,
+            ([], b'8') => EscCommand::RestoreCursor,
+            ([], b'c') => EscCommand::FullReset,
+            ([], b'D') => EscCommand::Index,
+            ([], b'E') => EscCommand::NextLine,
+            ([], b'M') => EscCommand::ReverseIndex,
+            ([], b'H') => EscCommand::TabSet,
+            ([], b'N') => EscCommand::SingleShift2,
+            ([], b'O') => EscCommand::SingleShift3,
+            ([], b'=') => EscCommand::KeypadApplicationMode,
+            ([], b'>') => EscCommand::KeypadNumericMode,
+            ([b'#'], b'8') => EscCommand::AlignmentTest,
+            ([designator, rest @ ..], charset) if rest.len() <= 1 => {
+                match charset_designator(*designator) {
+                    Some((slot, size)) => EscCommand::DesignateCharset {
+                        slot,
+                        size,
+                        charset_intermediate: rest.first().copied(),
+                        charset,
+                    },
+                    None => EscCommand::Unknown { cmd, intermediates },
+                }
+            }
+            _ => EscCommand::Unknown { cmd, intermediates },
+        }
+    }
+}
+
+/// One of the four character set slots that [`EscCommand::DesignateCharset`]
+/// can assign to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharsetSlot {
+    /// Designated by `(`, which accepts only 94-character sets.
+    G0,
+    /// Designated by `)`, or `-` for a 96-character set.
+    G1,
+    /// Designated by `*`, or `.` for a 96-character set.
+    G2,
+    /// Designated by `+`, or `/` for a 96-character set.
+    G3,
+}
+
+/// The number of characters in a character set designated by
+/// [`EscCommand::DesignateCharset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharsetSize {
+    /// A set of 94 characters, occupying 0x21 through 0x7E.
+    Chars94,
+    /// A set of 96 characters, occupying 0x20 through 0x7F.
+    Chars96,
+}
+
+/// Returns the slot and set size selected by the first intermediate
+/// character of an SCS sequence.
+fn charset_designator(c: u8) -> Option<(CharsetSlot, CharsetSize)> {
+    match c {
+        b'(' => Some((CharsetSlot::G0, CharsetSize::Chars94)),
+        b')' => Some((CharsetSlot::G1, CharsetSize::Chars94)),
+        b'*' => Some((CharsetSlot::G2, CharsetSize::Chars94)),
+        b'+' => Some((CharsetSlot::G3, CharsetSize::Chars94)),
+        // There is no 96-character designator for G0, because a 96-character
+        // set would overlap the space and delete characters.
+        b'-' => Some((CharsetSlot::G1, CharsetSize::Chars96)),
+        b'.' => Some((CharsetSlot::G2, CharsetSize::Chars96)),
+        b'/' => Some((CharsetSlot::G3, CharsetSize::Chars96)),
+        _ => None,
+    }
+}
+
+impl<'m> VtEvent<'m> {
+    /// Decodes the event as an [`EscCommand`] if it is a
+    /// [`VtEvent::DispatchEsc`], or returns `None` for any other event.
+    pub fn esc_command(&self) -> Option<EscCommand<'m>> {
+        match *self {
+            VtEvent::DispatchEsc { cmd, intermediates } => {
+                Some(EscCommand::new(cmd, intermediates))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use u8char::u8char;
+
+use crate::{VtErrorKind, VtEvent, VtParams, VtState};
+
+/// Receives the events produced by a [`crate::VtMachine`] as method calls.
+///
+/// This is an alternative to consuming [`VtEvent`] values directly, with one
+/// method per event type. Pass an implementation to
+/// [`crate::VtMachine::advance`] to have the machine call the methods
+/// directly, or use [`VtEvent::dispatch_to`] to route individual events.
+///
+/// All of the methods have default implementations that do nothing, so
+/// implementers only need to override the methods for events they care about.
+pub trait VtHandler {
+    /// Print a literal character at the current cursor position.
+    ///
+    /// Corresponds to [`VtEvent::Print`].
+    fn print(&mut self, c: u8char) {
+        let _ = c;
+    }
+
+    /// Marks the end of a series of consecutive calls to [`Self::print`].
+    ///
+    /// Corresponds to [`VtEvent::PrintEnd`].
+    fn print_end(&mut self) {}
+
+    /// Execute an appropriate action for the given control character.
+    ///
+    /// Corresponds to [`VtEvent::ExecuteCtrl`].
+    fn execute(&mut self, ctrl: u8) {
+        let _ = ctrl;
+    }
+
+    /// Execute an appropriate action for the given control sequence.
+    ///
+    /// Corresponds to [`VtEvent::DispatchCsi`].
+    fn csi_dispatch(
+        &mut self,
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParams<'_>,
+        intermediates: &[u8],
+        truncated: bool,
+    ) {
+        let _ = (cmd, private_marker, params, intermediates, truncated);
+    }
+
+    /// Execute an appropriate action for the given escape sequence.
+    ///
+    /// Corresponds to [`VtEvent::DispatchEsc`].
+    fn esc_dispatch(&mut self, cmd: u8, intermediates: &[u8]) {
+        let _ = (cmd, intermediates);
+    }
+
+    /// Reports the beginning of a device control string.
+    ///
+    /// Corresponds to [`VtEvent::DcsStart`].
+    fn dcs_hook(
+        &mut self,
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParams<'_>,
+        intermediates: &[u8],
+        truncated: bool,
+    ) {
+        let _ = (cmd, private_marker, params, intermediates, truncated);
+    }
+
+    /// Reports a literal character from within a device control string.
+    ///
+    /// Corresponds to [`VtEvent::DcsChar`].
+    fn dcs_put(&mut self, c: u8char) {
+        let _ = c;
+    }
+
+    /// Marks the end of a device control string.
+    ///
+    /// Corresponds to [`VtEvent::DcsEnd`].
+    fn dcs_unhook(&mut self, terminator: u8) {
+        let _ = terminator;
+    }
+
+    /// Reports the beginning of an operating system command.
+    ///
+    /// Corresponds to [`VtEvent::OscStart`].
+    fn osc_start(&mut self, introducer: u8) {
+        let _ = introducer;
+    }
+
+    /// Reports a literal character from within an operating system command.
+    ///
+    /// Corresponds to [`VtEvent::OscChar`].
+    fn osc_put(&mut self, c: u8char) {
+        let _ = c;
+    }
+
+    /// Marks the end of an operating system command.
+    ///
+    /// Corresponds to [`VtEvent::OscEnd`].
+    fn osc_end(&mut self, terminator: u8) {
+        let _ = terminator;
+    }
+
+    /// Reports the beginning of an SOS, PM, or APC string.
+    ///
+    /// Corresponds to [`VtEvent::SosPmApcStart`].
+    fn sos_pm_apc_start(&mut self, introducer: u8) {
+        let _ = introducer;
+    }
+
+    /// Reports a literal character from within an SOS, PM, or APC string.
+    ///
+    /// Corresponds to [`VtEvent::SosPmApcChar`].
+    fn sos_pm_apc_put(&mut self, c: u8char) {
+        let _ = c;
+    }
+
+    /// Marks the end of an SOS, PM, or APC string.
+    ///
+    /// Corresponds to [`VtEvent::SosPmApcEnd`].
+    fn sos_pm_apc_end(&mut self, terminator: u8) {
+        let _ = terminator;
+    }
+
+    /// Reports a character that was not expected in the machine's current
+    /// state.
+    ///
+    /// Corresponds to [`VtEvent::Error`].
+    fn error(&mut self, c: u8char, kind: VtErrorKind, state: VtState) {
+        let _ = (c, kind, state);
+    }
+}
+
+impl<'m> VtEvent<'m> {
+    /// Passes the event to the corresponding method of the given handler.
+    #[inline]
+    pub fn dispatch_to<H: VtHandler + ?Sized>(self, handler: &mut H) {
+        match self {
+            VtEvent::Print(c) => handler.print(c),
+            VtEvent::PrintEnd => handler.print_end(),
+            VtEvent::ExecuteCtrl(ctrl) => handler.execute(ctrl),
+            VtEvent::DispatchCsi {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            } => handler.csi_dispatch(cmd, private_marker, params, intermediates, truncated),
+            VtEvent::DispatchEsc { cmd, intermediates } => handler.esc_dispatch(cmd, intermediates),
+            VtEvent::DcsStart {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            } => handler.dcs_hook(cmd, private_marker, params, intermediates, truncated),
+            VtEvent::DcsChar(c) => handler.dcs_put(c),
+            VtEvent::DcsEnd(terminator) => handler.dcs_unhook(terminator),
+            VtEvent::OscStart(introducer) => handler.osc_start(introducer),
+            VtEvent::OscChar(c) => handler.osc_put(c),
+            VtEvent::OscEnd(terminator) => handler.osc_end(terminator),
+            VtEvent::SosPmApcStart(introducer) => handler.sos_pm_apc_start(introducer),
+            VtEvent::SosPmApcChar(c) => handler.sos_pm_apc_put(c),
+            VtEvent::SosPmApcEnd(terminator) => handler.sos_pm_apc_end(terminator),
+            VtEvent::Error { c, kind, state } => handler.error(c, kind, state),
+        }
+    }
+}
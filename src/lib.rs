@@ -22,14 +22,66 @@
 //! then returns a series of events that the character caused.
 //!
 //! If you are recieving the terminal data as a byte stream (likely!) then
-//! you can use [`::u8char::stream::U8CharStream`] to translate the incoming
-//! bytes into a series of [`u8char`] values to pass to your `VtMachine`. The
-//! "report" example demonstrates that usage pattern.
+//! you can pass chunks of bytes directly to [`VtMachine::write_bytes`], which
+//! deals with the UTF-8 decoding and delivers the resulting events to a
+//! callback. The "report" example demonstrates that usage pattern.
+//! Alternatively, you can use [`::u8char::stream::U8CharStream`] to translate
+//! the incoming bytes into a series of [`u8char`] values yourself.
+//!
+//! Instead of matching on each [`VtEvent`], callers can implement
+//! [`VtHandler`] and pass it to [`VtMachine::advance`] to receive each
+//! event as a method call.
+//!
+//! The raw events can be decoded further into typed commands, such as by
+//! [`VtEvent::csi_command`] for control sequences, [`SgrAttributes`] for
+//! their graphic rendition parameters, [`VtEvent::esc_command`] for
+//! escape sequences, [`OscCommand::parse`] for operating system commands, and
+//! [`DcsCommand::new`] for device control strings.
+//!
+//! Each [`VtEvent`] borrows from the machine that produced it, so use
+//! [`VtEvent::into_owned`] to obtain a [`VtEventOwned`] that can be kept while
+//! more input is written, such as to send it to another thread.
+//!
+//! With the `alloc` feature enabled, `VtBufferedMachine` wraps a
+//! [`VtMachine`] to deliver each complete operating system command or device
+//! control string as a single event, instead of one event per character,
+//! and `SixelDecoder` decodes sixel graphics into an RGBA pixel buffer.
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+mod buffered;
+mod csi;
+mod dcs;
+mod esc;
+mod handler;
 mod machine;
+mod osc;
+mod owned;
+mod params;
+mod sgr;
+#[cfg(feature = "alloc")]
+mod sixel;
+mod utf8;
 
-pub use machine::{VtEvent, VtMachine};
+#[cfg(feature = "alloc")]
+pub use buffered::{VtBufferedEvent, VtBufferedMachine, VtDcsHeader};
+pub use csi::{CsiCommand, EraseMode};
+pub use dcs::{DcsCommand, HexBytes, HexData, HexList, UdkDefinitions};
+pub use esc::{CharsetSize, CharsetSlot, EscCommand};
+pub use handler::VtHandler;
+pub use machine::{VtErrorKind, VtEvent, VtMachine, VtSnapshotError, VtState};
+pub use osc::{
+    Base64Bytes, Base64Data, ClipboardData, DynamicColor, OscColorPairs, OscColorSpec, OscCommand,
+    ShellMark,
+};
+pub use owned::{VtEventOwned, VtIntermediatesOwned, VtParamsOwned};
+pub use params::{VtParamGroups, VtParams};
+pub use sgr::{SgrAttribute, SgrAttributes, SgrColor, TextStyle, UnderlineStyle};
+#[cfg(feature = "alloc")]
+pub use sixel::{SixelDecoder, SixelImage};
 
 #[cfg(test)]
 mod tests;
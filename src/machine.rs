@@ -2,6 +2,10 @@ use core::mem::MaybeUninit;
 
 use u8char::u8char;
 
+use crate::params::{is_valid_flags, ParamsBuf};
+use crate::utf8::Utf8Stream;
+use crate::{VtHandler, VtParams};
+
 /// Virtual terminal state machine.
 ///
 /// This is the main type in this crate, which takes Unicode scalar values
@@ -11,41 +15,134 @@ use u8char::u8char;
 /// `VtMachine` implements a _Unicode-native_ terminal state machine that does
 /// not support any legacy character encodings. If working with a raw byte
 /// stream, such as from a pseudoterminal provided by the host OS, the caller
-/// must first interpret the bytes as UTF-8 sequences and provide the result to
-/// either [`VtMachine::write_u8char`].
+/// can either pass the bytes to [`VtMachine::write_bytes`] or interpret the
+/// bytes as UTF-8 sequences itself and provide the result to
+/// [`VtMachine::write_u8char`].
 ///
 /// This implementation is not suitable for emulating a legacy hardware video
 /// terminal that used switchable character sets.
-pub struct VtMachine {
+///
+/// The machine can retain up to `PARAMS` parameter values and up to
+/// `INTERMEDIATES` intermediate characters for each sequence. Any parameters
+/// beyond that limit are discarded and the sequence is reported as truncated,
+/// while a sequence with too many intermediate characters is reported as
+/// [`VtEvent::Error`] instead of being dispatched. Callers that need to handle
+/// unusually-long sequences can choose larger capacities using
+/// [`VtMachine::new_with_capacity`].
+///
+/// The machine can be cloned to parse ahead speculatively, or its parser
+/// state can be saved using [`VtMachine::snapshot`] and later loaded into
+/// another machine using [`VtMachine::restore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VtMachine<const PARAMS: usize = 16, const INTERMEDIATES: usize = 2> {
     state: State,
-    intermediates: VtIntermediates,
-    params: VtParams,
+    intermediates: VtIntermediates<INTERMEDIATES>,
+    params: ParamsBuf<PARAMS>,
+    private_marker: Option<u8>,
     in_literal_chunk: bool,
+    utf8: Utf8Stream,
+    osc_bel_terminator: bool,
+    c1_controls: bool,
+    sos_pm_apc_strings: bool,
+    error_reprocessing: bool,
 }
 
 impl VtMachine {
-    /// Constructs a new [`VtMachine`].
+    /// Constructs a new [`VtMachine`] with the default capacities.
     pub const fn new() -> Self {
+        Self::new_with_capacity()
+    }
+}
+
+impl<const PARAMS: usize, const INTERMEDIATES: usize> VtMachine<PARAMS, INTERMEDIATES> {
+    /// Constructs a new [`VtMachine`] with the capacities given in its
+    /// type parameters, such as `VtMachine::<32, 4>::new_with_capacity()`.
+    pub const fn new_with_capacity() -> Self {
         Self {
             state: State::Literal,
             intermediates: VtIntermediates::new(),
-            params: VtParams::new(),
+            params: ParamsBuf::new(),
+            private_marker: None,
             in_literal_chunk: false,
+            utf8: Utf8Stream::new(),
+            osc_bel_terminator: true,
+            c1_controls: true,
+            sos_pm_apc_strings: true,
+            error_reprocessing: false,
         }
     }
 
+    /// Returns the machine with the handling of BEL (0x07) inside an operating
+    /// system command enabled or disabled.
+    ///
+    /// By default BEL terminates an operating system command, producing a
+    /// [`VtEvent::OscEnd`] reporting 0x07, as is common practice following
+    /// xterm. Disabling this gives the strict ECMA-48 behavior where BEL
+    /// is ignored inside an operating system command, and so only the string
+    /// terminator can end it.
+    pub const fn with_osc_bel_terminator(mut self, enabled: bool) -> Self {
+        self.osc_bel_terminator = enabled;
+        self
+    }
+
+    /// Returns the machine with recognition of the C1 control characters
+    /// U+0080 through U+009F enabled or disabled.
+    ///
+    /// By default these characters are treated as control functions, such as
+    /// U+009B as the control sequence introducer. Many terminals disable this
+    /// because those code points collide with characters in some legacy
+    /// encodings, in which case they are printed as literal characters
+    /// instead and only the seven-bit escape sequence equivalents are
+    /// recognized.
+    pub const fn with_c1_controls(mut self, enabled: bool) -> Self {
+        self.c1_controls = enabled;
+        self
+    }
+
+    /// Returns the machine with reporting of SOS, PM, and APC strings enabled
+    /// or disabled.
+    ///
+    /// By default the content of these strings is reported using
+    /// [`VtEvent::SosPmApcStart`], [`VtEvent::SosPmApcChar`], and
+    /// [`VtEvent::SosPmApcEnd`]. Callers that have no use for them can
+    /// disable this to have the machine ignore them entirely.
+    pub const fn with_sos_pm_apc_strings(mut self, enabled: bool) -> Self {
+        self.sos_pm_apc_strings = enabled;
+        self
+    }
+
+    /// Returns the machine with reprocessing of unexpected characters enabled
+    /// or disabled.
+    ///
+    /// When a sequence is interrupted by a character that cannot appear in
+    /// it, the machine reports [`VtEvent::Error`] with
+    /// [`VtErrorKind::UnexpectedChar`] and abandons the sequence. By default
+    /// the character is then discarded. Enabling this instead processes the
+    /// character again as if it had appeared outside of any sequence, which
+    /// is what many real terminals do, so that `ESC[1é` prints `é`.
+    pub const fn with_error_reprocessing(mut self, enabled: bool) -> Self {
+        self.error_reprocessing = enabled;
+        self
+    }
+
     /// Consumes a single unicode scalar value given as a [`u8char`], returning
     /// a series of events that the character causes.
     ///
     /// The caller should consume the entire iterator in order to stay properly
     /// synchronized with the `VtMachine`.
     pub fn write_u8char<'m>(&'m mut self, c: u8char) -> impl Iterator<Item = VtEvent<'m>> {
+        self.transition(c)
+    }
+
+    fn transition<'m>(&'m mut self, c: u8char) -> Transition<'m> {
         // All of the special state transitions and actions are triggered by
-        // bytes in the ASCII range, so we will match those based on only the
-        // first byte of the UTF-8 character. For values less than 128 these
-        // bytes will be the whole represented character, and we're not going
-        // to match any values >=128.
-        let fb = c.first_byte();
+        // bytes in the ASCII range or by the C1 control characters, so we
+        // will match those based on only a single byte. For values less than
+        // 128 the first byte of the UTF-8 character is the whole represented
+        // character, and control_byte maps the C1 controls to their
+        // equivalent byte values. We're not going to match any other
+        // values >=128.
+        let fb = self.control_byte(c);
 
         // Some characters have the same effect regardless of the current state.
         match fb {
@@ -56,10 +153,16 @@ impl VtMachine {
                 return self.change_state(State::Literal, Action::None, c);
             }
             b'\x1b' => {
+                if let Some(state) = self.state.string_escape() {
+                    // This might be the start of the seven-bit string
+                    // terminator, so we can't end the string yet.
+                    self.state = state;
+                    return self.no_change();
+                }
                 return self.change_state(State::Escape, Action::None, c);
             }
             b'\x98' | b'\x9e' | b'\x9f' => {
-                return self.change_state(State::IgnoreUntilSt, Action::None, c);
+                return self.change_state(self.sos_pm_apc_state(), Action::None, c);
             }
             b'\x90' => {
                 return self.change_state(State::DevCtrlStart, Action::None, c);
@@ -112,7 +215,7 @@ impl VtMachine {
                     return self.change_state(State::DevCtrlStart, Action::None, c);
                 }
                 b'\x58' | b'\x5e' | b'\x5f' => {
-                    return self.change_state(State::IgnoreUntilSt, Action::None, c);
+                    return self.change_state(self.sos_pm_apc_state(), Action::None, c);
                 }
                 _ => return self.error(c),
             },
@@ -127,7 +230,7 @@ impl VtMachine {
                     return self.just_action(Action::Collect, c);
                 }
                 b'\x30'..=b'\x7e' => {
-                    return self.change_state(State::Literal, Action::EscDispatch, c);
+                    return self.dispatch(Action::EscDispatch, c);
                 }
                 _ => return self.error(c),
             },
@@ -141,17 +244,14 @@ impl VtMachine {
                 b'\x20'..=b'\x2f' => {
                     return self.change_state(State::CtrlIntermediate, Action::Collect, c);
                 }
-                b'\x3a' => {
-                    return self.change_state(State::CtrlMalformed, Action::None, c);
-                }
-                b'\x30'..=b'\x39' | b'\x3b' => {
+                b'\x30'..=b'\x3b' => {
                     return self.change_state(State::CtrlParam, Action::Param, c);
                 }
                 b'\x3c'..=b'\x3f' => {
-                    return self.change_state(State::CtrlParam, Action::Collect, c);
+                    return self.change_state(State::CtrlParam, Action::Marker, c);
                 }
                 b'\x40'..=b'\x7e' => {
-                    return self.change_state(State::Literal, Action::CsiDispatch, c);
+                    return self.dispatch(Action::CsiDispatch, c);
                 }
                 _ => return self.error(c),
             },
@@ -159,20 +259,20 @@ impl VtMachine {
                 b'\x00'..=b'\x17' | b'\x19' | b'\x1c'..=b'\x1f' => {
                     return self.just_action(Action::Execute, c);
                 }
-                b'\x30'..=b'\x39' | b'\x3b' => {
+                b'\x30'..=b'\x3b' => {
                     return self.just_action(Action::Param, c);
                 }
                 b'\x7f' => {
                     return self.no_change(); // Ignored
                 }
-                b'\x3a' | b'\x3c'..=b'\x3f' => {
+                b'\x3c'..=b'\x3f' => {
                     return self.change_state(State::CtrlMalformed, Action::None, c);
                 }
                 b'\x20'..=b'\x2f' => {
                     return self.change_state(State::CtrlIntermediate, Action::Collect, c);
                 }
                 b'\x40'..=b'\x7e' => {
-                    return self.change_state(State::Literal, Action::CsiDispatch, c);
+                    return self.dispatch(Action::CsiDispatch, c);
                 }
                 _ => return self.error(c),
             },
@@ -190,7 +290,7 @@ impl VtMachine {
                     return self.change_state(State::CtrlMalformed, Action::None, c);
                 }
                 b'\x40'..=b'\x7e' => {
-                    return self.change_state(State::Literal, Action::CsiDispatch, c);
+                    return self.dispatch(Action::CsiDispatch, c);
                 }
                 _ => return self.error(c),
             },
@@ -220,10 +320,10 @@ impl VtMachine {
                     return self.change_state(State::DevCtrlParam, Action::Param, c);
                 }
                 b'\x3c'..=b'\x3f' => {
-                    return self.change_state(State::DevCtrlParam, Action::Collect, c);
+                    return self.change_state(State::DevCtrlParam, Action::Marker, c);
                 }
                 b'\x40'..=b'\x7e' => {
-                    return self.change_state(State::DevCtrlPassthru, Action::None, c);
+                    return self.dcs_passthru(c);
                 }
                 _ => return self.error(c),
             },
@@ -241,7 +341,7 @@ impl VtMachine {
                     return self.change_state(State::DevCtrlIntermediate, Action::Collect, c);
                 }
                 b'\x40'..=b'\x7e' => {
-                    return self.change_state(State::DevCtrlPassthru, Action::None, c);
+                    return self.dcs_passthru(c);
                 }
                 _ => return self.error(c),
             },
@@ -256,40 +356,72 @@ impl VtMachine {
                     return self.change_state(State::DevCtrlMalformed, Action::None, c);
                 }
                 b'\x40'..=b'\x7e' => {
-                    return self.change_state(State::DevCtrlPassthru, Action::None, c);
+                    return self.dcs_passthru(c);
                 }
                 _ => return self.error(c),
             },
             State::DevCtrlPassthru => match fb {
-                b'\x00'..=b'\x17' | b'\x19' | b'\x1c'..=b'\x1f' | b'\x20'..=b'\x7e' => {
-                    return self.just_action(Action::Put, c);
-                }
                 b'\x7f' => {
                     return self.no_change(); // Ignored
                 }
-                _ => return self.error(c),
-            },
-            State::DevCtrlMalformed => match fb {
-                b'\x00'..=b'\x17' | b'\x19' | b'\x1c'..=b'\x1f' | b'\x20'..=b'\x7f' => {
-                    return self.no_change(); // Ignored
+                _ => {
+                    // This includes any non-ASCII character, since the C1
+                    // controls were already handled above.
+                    return self.just_action(Action::Put, c);
                 }
-                _ => return self.error(c),
             },
+            State::DevCtrlMalformed => {
+                // Nothing ends this state except the universal transitions
+                // handled above, so everything else is ignored.
+                return self.no_change();
+            }
             State::OsCmd => match fb {
+                b'\x07' if self.osc_bel_terminator => {
+                    return self.change_state(State::Literal, Action::None, c);
+                }
                 b'\x00'..=b'\x17' | b'\x19' | b'\x1c'..=b'\x1f' => {
                     return self.no_change(); // Ignored
                 }
-                b'\x20'..=b'\x7f' => {
+                _ => {
                     return self.just_action(Action::OscPut, c);
                 }
-                _ => return self.error(c),
             },
-            State::IgnoreUntilSt => match fb {
-                b'\x00'..=b'\x17' | b'\x19' | b'\x1c'..=b'\x1f' | b'\x20'..=b'\x7f' => {
+            State::SosPmApcStr => match fb {
+                b'\x00'..=b'\x17' | b'\x19' | b'\x1c'..=b'\x1f' => {
                     return self.no_change(); // Ignored
                 }
-                _ => return self.error(c),
+                _ => {
+                    return self.just_action(Action::SosPmApcPut, c);
+                }
             },
+            State::IgnoreUntilSt => {
+                return self.no_change(); // Ignored
+            }
+            State::OsCmdEscape
+            | State::DevCtrlPassthruEscape
+            | State::SosPmApcStrEscape
+            | State::IgnoreUntilStEscape => {
+                match fb {
+                    b'\x5c' => {
+                        // ESC \ is the seven-bit form of the string terminator,
+                        // so we report it the same way as its C1 equivalent.
+                        let exit_event = self.state_exit_event(self.state, b'\x9c');
+                        self.state = State::Literal;
+                        return Transition::new([exit_event]);
+                    }
+                    _ => {
+                        // Any other character means that the ESC was the
+                        // start of some other sequence, and so the string
+                        // ended there and we handle this character as the
+                        // next in the new escape sequence.
+                        let exit_event = self.state_exit_event(self.state, b'\x1b');
+                        self.state = State::Escape;
+                        self.intermediates.clear();
+                        self.params.clear();
+                        return self.transition(c).with_prefix(exit_event);
+                    }
+                }
+            }
         }
     }
 
@@ -304,38 +436,285 @@ impl VtMachine {
         self.write_u8char(u8char::from_char(c))
     }
 
+    /// Consumes a chunk of UTF-8 bytes, passing each of the events they cause
+    /// to the given callback.
+    ///
+    /// The chunk boundaries do not need to align with character boundaries:
+    /// an incomplete UTF-8 sequence at the end of `bytes` is retained and
+    /// completed by the next call. Invalid sequences are treated as
+    /// U+FFFD REPLACEMENT CHARACTER.
+    ///
+    /// Use [`Self::write_bytes_end`] instead of [`Self::write_end`] at the end
+    /// of the stream so that any incomplete sequence gets reported.
+    pub fn write_bytes(&mut self, bytes: &[u8], mut f: impl FnMut(VtEvent<'_>)) {
+        // The stream is copied out so that the callback can borrow the
+        // machine, and is then written back with any new pending bytes.
+        let mut utf8 = self.utf8;
+        utf8.write(bytes, |c| {
+            for event in self.write_u8char(c) {
+                f(event);
+            }
+        });
+        self.utf8 = utf8;
+    }
+
+    /// Consumes a chunk of UTF-8 bytes, calling the methods of the given
+    /// handler for each of the events they cause.
+    ///
+    /// This is the same as [`Self::write_bytes`] except that events are
+    /// delivered to a [`VtHandler`] instead of to a callback.
+    pub fn advance<H: VtHandler + ?Sized>(&mut self, handler: &mut H, bytes: &[u8]) {
+        self.write_bytes(bytes, |event| event.dispatch_to(handler));
+    }
+
+    /// Tells the [`VtMachine`] that no more bytes are expected from a stream
+    /// previously passed to [`Self::advance`], calling the methods of the
+    /// given handler for any final events.
+    pub fn advance_end<H: VtHandler + ?Sized>(&mut self, handler: &mut H) {
+        self.write_bytes_end(|event| event.dispatch_to(handler));
+    }
+
+    /// Tells the [`VtMachine`] that no more bytes are expected from a stream
+    /// previously passed to [`Self::write_bytes`], passing any final events
+    /// to the given callback.
+    ///
+    /// This is like [`Self::write_end`] except that it first reports any
+    /// incomplete UTF-8 sequence as U+FFFD REPLACEMENT CHARACTER.
+    pub fn write_bytes_end(&mut self, mut f: impl FnMut(VtEvent<'_>)) {
+        let mut utf8 = self.utf8;
+        utf8.end(|c| {
+            for event in self.write_u8char(c) {
+                f(event);
+            }
+        });
+        self.utf8 = utf8;
+        for event in self.write_end() {
+            f(event);
+        }
+    }
+
     /// Tells the [`VtMachine`] that no more bytes are expected, such as if
     /// the stream that the data is arriving from is closed from the writer
     /// end.
     ///
     /// This can potentially return some final events caused by ending sequences
-    /// that had not yet been explicitly terminated.
+    /// that had not yet been explicitly terminated. In particular, a string
+    /// that is still open produces [`VtEvent::OscEnd`], [`VtEvent::DcsEnd`],
+    /// or [`VtEvent::SosPmApcEnd`] reporting [`VtEvent::END_OF_STREAM`] as its
+    /// terminator. Any other incomplete sequence is discarded.
     ///
     /// It's okay to keep using the [`VtMachine`] after calling this function,
     /// but any subsequent character written will be treated as if it is the
     /// first character in a new stream.
     pub fn write_end(&mut self) -> impl Iterator<Item = VtEvent<'static>> {
-        self.state = State::Literal;
-        self.intermediates.clear();
-        self.params.clear();
+        let exit_event = self.state_exit_event(self.state, VtEvent::END_OF_STREAM);
         let event = if self.in_literal_chunk {
-            self.in_literal_chunk = false;
             Some(VtEvent::PrintEnd)
         } else {
             None
         };
-        Transition::new([event])
+        self.reset();
+        Transition::new([exit_event, event])
+    }
+
+    /// Returns the state that the machine is currently in.
+    ///
+    /// For example, a terminal can check for [`VtState::Escape`] to decide
+    /// whether a lone ESC typed at the keyboard is still waiting for the
+    /// rest of a sequence.
+    pub fn state(&self) -> VtState {
+        self.state.public()
+    }
+
+    /// Returns true if the machine is not part way through any sequence,
+    /// and so the next character will be handled as if it were the first.
+    ///
+    /// This does not consider any incomplete UTF-8 sequence retained by
+    /// [`Self::write_bytes`].
+    pub fn is_ground(&self) -> bool {
+        self.state == State::Literal
+    }
+
+    /// Returns the machine to its initial state without producing any
+    /// events, discarding any incomplete sequence.
+    ///
+    /// Unlike [`Self::write_end`], this does not report the end of an open
+    /// string or of a series of printed characters, so it's suitable for
+    /// performing a hard reset such as RIS. The options chosen when the
+    /// machine was constructed are retained.
+    pub fn reset(&mut self) {
+        self.state = State::Literal;
+        self.intermediates.clear();
+        self.params.clear();
+        self.private_marker = None;
+        self.in_literal_chunk = false;
+        self.utf8.clear();
+    }
+
+    /// The maximum number of bytes that [`Self::snapshot`] can write for a
+    /// machine with these capacities.
+    pub const SNAPSHOT_MAX_LEN: usize = 13 + INTERMEDIATES + 3 * PARAMS;
+
+    /// Writes a compact binary encoding of the machine's parser state into
+    /// the given buffer, returning the number of bytes written.
+    ///
+    /// The encoding includes the current state, any parameters, private-use
+    /// marker and intermediate characters collected so far, whether a series
+    /// of printed characters is in progress, and any incomplete UTF-8
+    /// sequence retained by [`Self::write_bytes`]. It does not include the
+    /// options chosen when the machine was constructed.
+    ///
+    /// The buffer must have space for the whole encoding, which is never
+    /// longer than [`Self::SNAPSHOT_MAX_LEN`].
+    pub fn snapshot(&self, buf: &mut [u8]) -> Result<usize, VtSnapshotError> {
+        let mut flags = 0;
+        if self.in_literal_chunk {
+            flags |= SNAPSHOT_IN_LITERAL_CHUNK;
+        }
+        if self.params.is_truncated() {
+            flags |= SNAPSHOT_PARAMS_TRUNCATED;
+        }
+        if self.intermediates.is_overrun() {
+            flags |= SNAPSHOT_INTERMEDIATES_OVERRUN;
+        }
+        let intermediates = self.intermediates.chars();
+        let params = self.params.params();
+        let pending = self.utf8.pending();
+
+        let mut w = SnapshotWriter { buf, pos: 0 };
+        w.u8(SNAPSHOT_VERSION)?;
+        w.u8(self.state as u8)?;
+        w.u8(flags)?;
+        w.u8(self.private_marker.unwrap_or(0))?;
+        w.len(intermediates.len())?;
+        w.bytes(intermediates)?;
+        w.len(params.values().len())?;
+        for (idx, v) in params.values().iter().enumerate() {
+            w.bytes(&v.to_le_bytes())?;
+            w.u8(params.flag(idx))?;
+        }
+        w.u8(pending.len() as u8)?;
+        w.bytes(pending)?;
+        Ok(w.pos)
+    }
+
+    /// Replaces the machine's parser state with one previously saved by
+    /// [`Self::snapshot`].
+    ///
+    /// The snapshot can come from a machine with different capacities, as
+    /// long as this machine has enough capacity for the parameters and
+    /// intermediate characters it contains. The options chosen when this
+    /// machine was constructed are retained.
+    ///
+    /// If this returns an error then the machine is left unchanged.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), VtSnapshotError> {
+        let mut r = SnapshotReader {
+            buf: snapshot,
+            pos: 0,
+        };
+        let version = r.u8()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(VtSnapshotError::UnsupportedVersion(version));
+        }
+        let state = *State::ALL
+            .get(r.u8()? as usize)
+            .ok_or(VtSnapshotError::Malformed)?;
+        let flags = r.u8()?;
+        if flags & !SNAPSHOT_ALL_FLAGS != 0 {
+            return Err(VtSnapshotError::Malformed);
+        }
+        let private_marker = match r.u8()? {
+            0 => None,
+            c @ b'\x3c'..=b'\x3f' => Some(c),
+            _ => return Err(VtSnapshotError::Malformed),
+        };
+
+        let mut intermediates = VtIntermediates::<INTERMEDIATES>::new();
+        let len = r.len()?;
+        if len > INTERMEDIATES {
+            return Err(VtSnapshotError::CapacityExceeded);
+        }
+        for c in r.bytes(len)? {
+            intermediates.push(*c);
+        }
+        if flags & SNAPSHOT_INTERMEDIATES_OVERRUN != 0 {
+            intermediates.set_overrun();
+        }
+
+        let mut params = ParamsBuf::<PARAMS>::new();
+        let len = r.len()?;
+        if len > PARAMS {
+            return Err(VtSnapshotError::CapacityExceeded);
+        }
+        for _ in 0..len {
+            let v = r.bytes(2)?;
+            let v = u16::from_le_bytes([v[0], v[1]]);
+            let flags = r.u8()?;
+            if !is_valid_flags(flags) {
+                return Err(VtSnapshotError::Malformed);
+            }
+            params.push(v, flags);
+        }
+        if flags & SNAPSHOT_PARAMS_TRUNCATED != 0 {
+            params.set_truncated();
+        }
+
+        let mut utf8 = Utf8Stream::new();
+        let len = r.u8()? as usize;
+        if !utf8.restore(r.bytes(len)?) {
+            return Err(VtSnapshotError::Malformed);
+        }
+        if r.pos != snapshot.len() {
+            return Err(VtSnapshotError::Malformed);
+        }
+
+        self.state = state;
+        self.intermediates = intermediates;
+        self.params = params;
+        self.private_marker = private_marker;
+        self.in_literal_chunk = flags & SNAPSHOT_IN_LITERAL_CHUNK != 0;
+        self.utf8 = utf8;
+        Ok(())
+    }
+
+    /// Returns the state to enter at the start of an SOS, PM, or APC string.
+    #[inline(always)]
+    fn sos_pm_apc_state(&self) -> State {
+        if self.sos_pm_apc_strings {
+            State::SosPmApcStr
+        } else {
+            State::IgnoreUntilSt
+        }
+    }
+
+    /// Returns the byte that represents the given character when it's acting
+    /// as a control character.
+    ///
+    /// For the C1 control characters U+0080 through U+009F, when enabled,
+    /// this is the character's scalar value. For all other characters this is
+    /// just the first byte of the UTF-8 encoding, which is the entire
+    /// character for ASCII and is otherwise a byte that won't match any
+    /// control function.
+    #[inline(always)]
+    fn control_byte(&self, c: u8char) -> u8 {
+        let scalar = c.to_char() as u32;
+        if self.c1_controls && (0x80..=0x9f).contains(&scalar) {
+            return scalar as u8;
+        }
+        c.first_byte()
     }
 
     fn action(&mut self, action: Action, c: u8char) -> Option<VtEvent<'static>> {
         match action {
             Action::Collect => self.intermediates.push(c.first_byte()),
+            Action::Marker => self.private_marker = Some(c.first_byte()),
             Action::Param => {
                 self.params.push_csi_char(c);
             }
-            Action::Clear | Action::Error => {
+            Action::Clear => {
                 self.intermediates.clear();
                 self.params.clear();
+                self.private_marker = None;
             }
             Action::Print => {}
             Action::Execute => {}
@@ -343,6 +722,8 @@ impl VtMachine {
             Action::Put => {}
             Action::OscStart => {}
             Action::OscPut => {}
+            Action::SosPmApcStart => {}
+            Action::SosPmApcPut => {}
             Action::CsiDispatch => {}
             Action::EscDispatch => {}
             Action::None => {}
@@ -359,19 +740,25 @@ impl VtMachine {
     fn action_event<'m>(&'m self, action: Action, c: u8char) -> Option<VtEvent<'m>> {
         match action {
             Action::Print => Some(VtEvent::Print(c)),
-            Action::Execute => Some(VtEvent::ExecuteCtrl(c.first_byte())),
+            Action::Execute => Some(VtEvent::ExecuteCtrl(self.control_byte(c))),
             Action::Hook => Some(VtEvent::DcsStart {
                 cmd: c.first_byte(),
-                params: &self.params.values(),
+                private_marker: self.private_marker,
+                params: self.params.params(),
                 intermediates: &self.intermediates.chars(),
+                truncated: self.params.is_truncated(),
             }),
             Action::Put => Some(VtEvent::DcsChar(c)),
-            Action::OscStart => Some(VtEvent::OscStart(c.first_byte())),
+            Action::OscStart => Some(VtEvent::OscStart(self.control_byte(c))),
             Action::OscPut => Some(VtEvent::OscChar(c)),
+            Action::SosPmApcStart => Some(VtEvent::SosPmApcStart(self.control_byte(c))),
+            Action::SosPmApcPut => Some(VtEvent::SosPmApcChar(c)),
             Action::CsiDispatch => Some(VtEvent::DispatchCsi {
                 cmd: c.first_byte(),
-                params: &self.params.values(),
+                private_marker: self.private_marker,
+                params: self.params.params(),
                 intermediates: &self.intermediates.chars(),
+                truncated: self.params.is_truncated(),
             }),
             Action::EscDispatch => Some(VtEvent::DispatchEsc {
                 cmd: c.first_byte(),
@@ -379,9 +766,9 @@ impl VtMachine {
             }),
             Action::None => None,
             Action::Collect => None,
+            Action::Marker => None,
             Action::Param => None,
             Action::Clear => None,
-            Action::Error => Some(VtEvent::Error(c)),
         }
     }
 
@@ -401,7 +788,17 @@ impl VtMachine {
         transition: Action,
         c: u8char,
     ) -> Transition<'m> {
-        let exit_event = self.state_exit_event(self.state, c);
+        let end = match self.state {
+            // If we were waiting to see whether an ESC was the start of a
+            // string terminator then it was actually the ESC that ended
+            // the string.
+            State::OsCmdEscape
+            | State::DevCtrlPassthruEscape
+            | State::SosPmApcStrEscape
+            | State::IgnoreUntilStEscape => b'\x1b',
+            _ => self.control_byte(c),
+        };
+        let exit_event = self.state_exit_event(self.state, end);
         self.state = state;
 
         let entry_action = self.state_entry_action(state);
@@ -427,27 +824,177 @@ impl VtMachine {
         ])
     }
 
+    /// Transitions back to the literal state, dispatching the sequence that
+    /// `c` completes using the given action.
+    ///
+    /// Following DEC's parser model, a sequence with more intermediate
+    /// characters than we have capacity for is not dispatched at all, because
+    /// a truncated list of intermediates would likely represent an entirely
+    /// different command. We report an error for the final character instead.
+    fn dispatch<'m>(&'m mut self, action: Action, c: u8char) -> Transition<'m> {
+        if self.intermediates.is_overrun() {
+            return self.error_with(c, VtErrorKind::TooManyIntermediates, State::Literal);
+        }
+        self.change_state(State::Literal, action, c)
+    }
+
+    /// Enters the data string portion of a device control string whose final
+    /// character is `c`.
+    ///
+    /// This has the same treatment of too many intermediate characters as
+    /// [`Self::dispatch`], except that the data string is then ignored.
+    fn dcs_passthru<'m>(&'m mut self, c: u8char) -> Transition<'m> {
+        if self.intermediates.is_overrun() {
+            return self.error_with(
+                c,
+                VtErrorKind::TooManyIntermediates,
+                State::DevCtrlMalformed,
+            );
+        }
+        self.change_state(State::DevCtrlPassthru, Action::None, c)
+    }
+
     fn state_entry_action(&mut self, state: State) -> Option<Action> {
         match state {
             State::Escape => Some(Action::Clear),
             State::CtrlStart => Some(Action::Clear),
             State::DevCtrlStart => Some(Action::Clear),
             State::OsCmd => Some(Action::OscStart),
+            State::SosPmApcStr => Some(Action::SosPmApcStart),
             State::DevCtrlPassthru => Some(Action::Hook),
             _ => None,
         }
     }
 
-    fn state_exit_event(&mut self, state: State, c: u8char) -> Option<VtEvent<'static>> {
+    fn state_exit_event(&mut self, state: State, end: u8) -> Option<VtEvent<'static>> {
         match state {
-            State::OsCmd => Some(VtEvent::OscEnd(c.first_byte())),
-            State::DevCtrlPassthru => Some(VtEvent::DcsEnd(c.first_byte())),
+            State::OsCmd | State::OsCmdEscape => Some(VtEvent::OscEnd(end)),
+            State::SosPmApcStr | State::SosPmApcStrEscape => Some(VtEvent::SosPmApcEnd(end)),
+            State::DevCtrlPassthru | State::DevCtrlPassthruEscape => Some(VtEvent::DcsEnd(end)),
             _ => None,
         }
     }
 
+    /// Abandons the current sequence because of the unexpected character `c`.
     fn error<'m>(&'m mut self, c: u8char) -> Transition<'m> {
-        self.change_state(State::Literal, Action::Error, c)
+        self.error_with(c, VtErrorKind::UnexpectedChar, State::Literal)
+    }
+
+    /// Reports an error of the given kind caused by `c`, and then abandons
+    /// the current sequence by entering the given state.
+    ///
+    /// This is only used in states that have no exit event and while not in
+    /// a literal chunk, so the error is the only event that the transition
+    /// itself produces.
+    fn error_with<'m>(&'m mut self, c: u8char, kind: VtErrorKind, state: State) -> Transition<'m> {
+        let event = VtEvent::Error {
+            c,
+            kind,
+            state: self.state.public(),
+        };
+        self.state = state;
+        self.intermediates.clear();
+        self.params.clear();
+        self.private_marker = None;
+        if self.error_reprocessing && kind == VtErrorKind::UnexpectedChar {
+            return self.transition(c).with_prefix(Some(event));
+        }
+        Transition::new([Some(event)])
+    }
+}
+
+/// The errors that can be returned by [`VtMachine::snapshot`] and
+/// [`VtMachine::restore`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtSnapshotError {
+    /// The buffer given to [`VtMachine::snapshot`] is too small to hold the
+    /// encoding.
+    BufferTooSmall,
+    /// The snapshot uses a version of the encoding that this version of the
+    /// crate does not support.
+    UnsupportedVersion(u8),
+    /// The snapshot is truncated or otherwise not a valid encoding.
+    Malformed,
+    /// The snapshot contains more parameters or intermediate characters than
+    /// the machine has capacity for.
+    CapacityExceeded,
+}
+
+impl core::fmt::Display for VtSnapshotError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            VtSnapshotError::BufferTooSmall => f.write_str("buffer too small for snapshot"),
+            VtSnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported snapshot version {v}")
+            }
+            VtSnapshotError::Malformed => f.write_str("malformed snapshot"),
+            VtSnapshotError::CapacityExceeded => {
+                f.write_str("snapshot exceeds the machine's capacity")
+            }
+        }
+    }
+}
+
+/// The version number written at the start of each snapshot, which must
+/// change whenever the encoding changes incompatibly.
+const SNAPSHOT_VERSION: u8 = 1;
+const SNAPSHOT_IN_LITERAL_CHUNK: u8 = 0b0000_0001;
+const SNAPSHOT_PARAMS_TRUNCATED: u8 = 0b0000_0010;
+const SNAPSHOT_INTERMEDIATES_OVERRUN: u8 = 0b0000_0100;
+const SNAPSHOT_ALL_FLAGS: u8 =
+    SNAPSHOT_IN_LITERAL_CHUNK | SNAPSHOT_PARAMS_TRUNCATED | SNAPSHOT_INTERMEDIATES_OVERRUN;
+
+struct SnapshotWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SnapshotWriter<'a> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), VtSnapshotError> {
+        let dst = self
+            .buf
+            .get_mut(self.pos..self.pos + bytes.len())
+            .ok_or(VtSnapshotError::BufferTooSmall)?;
+        dst.copy_from_slice(bytes);
+        self.pos += bytes.len();
+        Ok(())
+    }
+
+    fn u8(&mut self, v: u8) -> Result<(), VtSnapshotError> {
+        self.bytes(&[v])
+    }
+
+    fn len(&mut self, len: usize) -> Result<(), VtSnapshotError> {
+        // Capacities are const generic parameters that could in principle
+        // be larger than the format can represent.
+        let len = u16::try_from(len).map_err(|_| VtSnapshotError::CapacityExceeded)?;
+        self.bytes(&len.to_le_bytes())
+    }
+}
+
+struct SnapshotReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], VtSnapshotError> {
+        let ret = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(VtSnapshotError::Malformed)?;
+        self.pos += len;
+        Ok(ret)
+    }
+
+    fn u8(&mut self) -> Result<u8, VtSnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn len(&mut self) -> Result<usize, VtSnapshotError> {
+        let v = self.bytes(2)?;
+        Ok(u16::from_le_bytes([v[0], v[1]]) as usize)
     }
 }
 
@@ -457,18 +1004,27 @@ impl VtMachine {
 /// as a compromise to avoid a heap allocation for each new character, since
 /// we need a separate object to represent our potential borrow of data
 /// from inside the `VtMachine`.
+///
+/// The buffer has room for two more events added by [`Self::with_prefix`]:
+/// one for the end of a string interrupted by an ESC that starts another
+/// sequence, and one for an error whose character is then reprocessed in
+/// the ground state, which cannot itself report an error.
 struct Transition<'m> {
     next: usize,
-    events: [MaybeUninit<VtEvent<'m>>; 5],
+    events: [MaybeUninit<VtEvent<'m>>; TRANSITION_CAPACITY],
 }
 
+const TRANSITION_MAX_EVENTS: usize = 5;
+const TRANSITION_MAX_PREFIXES: usize = 2;
+const TRANSITION_CAPACITY: usize = TRANSITION_MAX_EVENTS + TRANSITION_MAX_PREFIXES;
+
 impl<'m> Transition<'m> {
     #[inline(always)]
     pub fn new<const N: usize>(events: [Option<VtEvent<'m>>; N]) -> Self {
-        assert!(const { N <= 5 });
+        assert!(const { N <= TRANSITION_MAX_EVENTS });
         let mut ret = Self {
-            next: 5,
-            events: [MaybeUninit::uninit(); 5],
+            next: TRANSITION_CAPACITY,
+            events: [MaybeUninit::uninit(); TRANSITION_CAPACITY],
         };
         for maybe_event in events.iter().rev() {
             if let Some(event) = maybe_event {
@@ -478,6 +1034,23 @@ impl<'m> Transition<'m> {
         }
         ret
     }
+
+    /// Adds an event to be returned before all of the others.
+    ///
+    /// There is always space for the prefixes described in the type's
+    /// documentation, so this can only fail if a new kind of prefix is
+    /// added without increasing `TRANSITION_MAX_PREFIXES`.
+    #[inline(always)]
+    pub fn with_prefix(mut self, event: Option<VtEvent<'m>>) -> Self {
+        if let Some(event) = event {
+            debug_assert!(self.next > 0, "no space for prefix event");
+            if let Some(next) = self.next.checked_sub(1) {
+                self.next = next;
+                self.events[next].write(event);
+            }
+        }
+        self
+    }
 }
 
 impl<'m> Iterator for Transition<'m> {
@@ -519,10 +1092,21 @@ pub enum VtEvent<'m> {
         /// The symbol at the end of the sequence representing the command
         /// to perform.
         cmd: u8,
-        /// The semicolon-separated integer parameters.
-        params: &'m [u16],
+        /// The private-use marker character that appeared at the start of the
+        /// parameters, if any: one of `<`, `=`, `>`, or `?`.
+        ///
+        /// For example, this is `Some(b'?')` for the DEC private mode
+        /// sequence `ESC[?1049h`.
+        private_marker: Option<u8>,
+        /// The semicolon-separated integer parameters, and any colon-separated
+        /// sub-parameters.
+        params: VtParams<'m>,
         /// Any intermediate characters that appeared inside the sequence.
         intermediates: &'m [u8],
+        /// Set if the sequence had more parameters than the [`VtMachine`] has
+        /// capacity for, in which case the extra ones are missing from
+        /// `params`.
+        truncated: bool,
     },
     DispatchEsc {
         cmd: u8,
@@ -534,14 +1118,24 @@ pub enum VtEvent<'m> {
     /// and then one [`VtEvent::DcsEnd`], when the input stream is valid.
     DcsStart {
         cmd: u8,
-        params: &'m [u16],
+        /// The private-use marker character that appeared at the start of the
+        /// parameters, if any: one of `<`, `=`, `>`, or `?`.
+        private_marker: Option<u8>,
+        params: VtParams<'m>,
         intermediates: &'m [u8],
+        /// Set if the sequence had more parameters than the [`VtMachine`] has
+        /// capacity for, in which case the extra ones are missing from
+        /// `params`.
+        truncated: bool,
     },
     /// Reports a literal character from within the "data string" portion of
     /// a device control string sequence.
     DcsChar(u8char),
     /// Marks the end of a device control string, reporting the character that
     /// ended it, which should be the "string terminator" character.
+    ///
+    /// The string terminator is always reported as its C1 form 0x9c, even
+    /// when it was written as the seven-bit sequence `ESC \`.
     DcsEnd(u8),
     /// Reports the beginning of an operating system command.
     ///
@@ -552,10 +1146,52 @@ pub enum VtEvent<'m> {
     OscChar(u8char),
     /// Marks the end of an operating system command, reporting the character
     /// that ended it.
+    ///
+    /// The string terminator is always reported as its C1 form 0x9c, even
+    /// when it was written as the seven-bit sequence `ESC \`.
     OscEnd(u8),
+    /// Reports the beginning of a "start of string", "privacy message", or
+    /// "application program command" string, reporting the character that
+    /// introduced it to distinguish between them.
+    ///
+    /// The introducer is `X` or 0x98 for SOS, `^` or 0x9e for PM, and `_` or
+    /// 0x9f for APC, the last of which is used by protocols such as the kitty
+    /// graphics protocol.
+    ///
+    /// Events of this type are followed by zero or more
+    /// [`VtEvent::SosPmApcChar`] and then one [`VtEvent::SosPmApcEnd`], when
+    /// the input stream is valid. These strings are ignored altogether if
+    /// disabled using [`VtMachine::with_sos_pm_apc_strings`].
+    SosPmApcStart(u8),
+    /// Reports a literal character from within an SOS, PM, or APC string.
+    SosPmApcChar(u8char),
+    /// Marks the end of an SOS, PM, or APC string, reporting the character
+    /// that ended it in the same way as [`VtEvent::OscEnd`].
+    SosPmApcEnd(u8),
     /// Emitted whenever the state machine encounters a character that is
     /// not expected in its current state.
-    Error(u8char),
+    ///
+    /// This is also emitted for the final character of a sequence that had
+    /// too many intermediate characters, in place of dispatching it.
+    Error {
+        /// The character that caused the error.
+        c: u8char,
+        /// The rule that the character violated.
+        kind: VtErrorKind,
+        /// The state the machine was in when the character arrived.
+        state: VtState,
+    },
+}
+
+impl<'m> VtEvent<'m> {
+    /// The terminator reported by [`VtEvent::OscEnd`], [`VtEvent::DcsEnd`],
+    /// and [`VtEvent::SosPmApcEnd`] for a string that was still open when
+    /// [`VtMachine::write_end`] was called, and so was aborted rather than
+    /// properly terminated.
+    ///
+    /// This is the C0 control character "end of transmission", which can
+    /// never terminate a string in the data stream itself.
+    pub const END_OF_STREAM: u8 = 0x04;
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -566,16 +1202,22 @@ enum Action {
     Put,
     OscStart,
     OscPut,
+    SosPmApcStart,
+    SosPmApcPut,
     CsiDispatch,
     EscDispatch,
     None,
     Collect,
+    Marker,
     Param,
     Clear,
-    Error,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// The states of the machine.
+///
+/// The order of these is part of the snapshot format, so new states must be
+/// added only at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum State {
     Literal,
     Escape,
@@ -590,104 +1232,159 @@ enum State {
     DevCtrlPassthru,
     DevCtrlMalformed,
     OsCmd,
+    SosPmApcStr,
     IgnoreUntilSt,
+    OsCmdEscape,
+    DevCtrlPassthruEscape,
+    SosPmApcStrEscape,
+    IgnoreUntilStEscape,
 }
 
-/// Zero or more `u16` values given as parameters in a control sequence, or similar.
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct VtParams {
-    buf: [u16; 16],
-    len: u8,
-}
+impl State {
+    /// All of the states, in the order used by the snapshot format.
+    const ALL: [State; 19] = [
+        State::Literal,
+        State::Escape,
+        State::EscapeIntermediate,
+        State::CtrlStart,
+        State::CtrlParam,
+        State::CtrlIntermediate,
+        State::CtrlMalformed,
+        State::DevCtrlStart,
+        State::DevCtrlParam,
+        State::DevCtrlIntermediate,
+        State::DevCtrlPassthru,
+        State::DevCtrlMalformed,
+        State::OsCmd,
+        State::SosPmApcStr,
+        State::IgnoreUntilSt,
+        State::OsCmdEscape,
+        State::DevCtrlPassthruEscape,
+        State::SosPmApcStrEscape,
+        State::IgnoreUntilStEscape,
+    ];
 
-impl VtParams {
-    /// Constructs a new zero-length [`VtParams`].
-    pub const fn new() -> Self {
-        Self {
-            buf: [0; 16],
-            len: 0,
+    /// Returns the public representation of the state.
+    fn public(self) -> VtState {
+        match self {
+            State::Literal => VtState::Ground,
+            State::Escape => VtState::Escape,
+            State::EscapeIntermediate => VtState::EscapeIntermediate,
+            State::CtrlStart => VtState::CsiEntry,
+            State::CtrlParam => VtState::CsiParam,
+            State::CtrlIntermediate => VtState::CsiIntermediate,
+            State::CtrlMalformed => VtState::CsiIgnore,
+            State::DevCtrlStart => VtState::DcsEntry,
+            State::DevCtrlParam => VtState::DcsParam,
+            State::DevCtrlIntermediate => VtState::DcsIntermediate,
+            State::DevCtrlPassthru | State::DevCtrlPassthruEscape => VtState::DcsPassthrough,
+            State::OsCmd | State::OsCmdEscape => VtState::OscString,
+            State::SosPmApcStr | State::SosPmApcStrEscape => VtState::SosPmApcString,
+            State::DevCtrlMalformed | State::IgnoreUntilSt | State::IgnoreUntilStEscape => {
+                VtState::IgnoredString
+            }
         }
     }
 
-    /// Attempts to push a new value.
+    /// Returns the state to use for an ESC character encountered in a string
+    /// state, or `None` if the state does not accept a string.
     ///
-    /// A [`VtParams`] has a capacity of 16 items, and so any pushes after
-    /// that capacity has been reached are silently ignored.
-    pub fn push(&mut self, v: u16) {
-        if (self.len as usize) == self.buf.len() {
-            return; // pushes beyond capacity are silently ignored
-        }
-        self.buf[self.len as usize] = v;
-        self.len += 1;
-    }
-
-    fn push_csi_char(&mut self, c: u8char) {
-        if c.first_byte() == b';' {
-            // Argument separator, so we start a new param.
-            self.push(0);
-        } else {
-            // The character must be a digit, then
-            if self.len == 0 {
-                self.push(0); // start our first param
-            }
-            let current = &mut self.buf[(self.len as usize) - 1];
-            let digit = (c.to_char() as u16) - ('0' as u16);
-            *current *= 10;
-            *current += digit;
+    /// The ESC might be the start of the string terminator `ESC \`, so we
+    /// need to wait for the next character to decide whether it's ending the
+    /// string normally or starting a new escape sequence.
+    fn string_escape(self) -> Option<State> {
+        match self {
+            State::OsCmd => Some(State::OsCmdEscape),
+            State::DevCtrlPassthru => Some(State::DevCtrlPassthruEscape),
+            State::SosPmApcStr => Some(State::SosPmApcStrEscape),
+            State::DevCtrlMalformed | State::IgnoreUntilSt => Some(State::IgnoreUntilStEscape),
+            _ => None,
         }
     }
+}
 
-    /// Discard all of the parameters, causing the object to then have length zero.
-    #[inline(always)]
-    pub fn clear(&mut self) {
-        self.len = 0;
-    }
-
-    /// Returns the parameter values as a slice of [`u16`] values.
-    #[inline(always)]
-    pub fn values(&self) -> &[u16] {
-        &self.buf[..(self.len as usize)]
-    }
+/// The kinds of error reported by [`VtEvent::Error`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VtErrorKind {
+    /// A character that cannot appear in the current sequence interrupted
+    /// it, and so the sequence was abandoned.
+    ///
+    /// This is the only kind of error that is affected by
+    /// [`VtMachine::with_error_reprocessing`].
+    UnexpectedChar,
+    /// A sequence had more intermediate characters than the machine has
+    /// capacity for, and so its final character was not dispatched.
+    TooManyIntermediates,
 }
 
-impl core::fmt::Debug for VtParams {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_tuple("VtParams")
-            .field(&&self.buf[..(self.len as usize)])
-            .finish()
-    }
+/// The publicly-visible states of a [`VtMachine`], named after the states in
+/// the DEC ANSI parser model that this crate is based on.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VtState {
+    /// Not in any sequence, so characters are printed.
+    Ground,
+    /// After an ESC character.
+    Escape,
+    /// After an ESC character and at least one intermediate character.
+    EscapeIntermediate,
+    /// After a control sequence introducer.
+    CsiEntry,
+    /// In the parameters of a control sequence.
+    CsiParam,
+    /// In the intermediate characters of a control sequence.
+    CsiIntermediate,
+    /// In a malformed control sequence, which will be ignored.
+    CsiIgnore,
+    /// After a device control string introducer.
+    DcsEntry,
+    /// In the parameters of a device control string.
+    DcsParam,
+    /// In the intermediate characters of a device control string.
+    DcsIntermediate,
+    /// In the data string of a device control string.
+    DcsPassthrough,
+    /// In an operating system command.
+    OscString,
+    /// In an SOS, PM, or APC string.
+    SosPmApcString,
+    /// In a string whose content is being ignored, which is either a
+    /// malformed device control string or an SOS, PM, or APC string when
+    /// those are disabled.
+    IgnoredString,
 }
 
 /// Zero or more intermediate characters that appeared as part of an
 /// escape sequence.
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct VtIntermediates {
-    buf: [u8; 2],
-    len: u8, // greater than length of buf means overrun
+#[derive(Clone, Copy)]
+struct VtIntermediates<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overrun: bool,
 }
 
-impl VtIntermediates {
-    const OVERRUN_LEN: usize = 3;
-
+impl<const N: usize> VtIntermediates<N> {
     /// Constructs a new zero-length [`VtIntermediates`].
     pub const fn new() -> Self {
         Self {
-            buf: [0; 2],
+            buf: [0; N],
             len: 0,
+            overrun: false,
         }
     }
 
     /// Attempts to push a new value.
     ///
-    /// A [`VtParams`] has a capacity of two characters, and so any pushes after
-    /// that capacity has been reached are silently ignored.
+    /// A [`VtIntermediates`] has a capacity of `N` characters, and so any
+    /// pushes after that capacity has been reached are ignored, but recorded
+    /// so that [`Self::is_overrun`] will return true.
     pub fn push(&mut self, c: u8) {
-        let len = self.len();
-        if len >= self.buf.len() {
-            self.len = Self::OVERRUN_LEN as u8;
+        if self.len >= self.buf.len() {
+            self.overrun = true;
             return;
         }
-        self.buf[len] = c;
+        self.buf[self.len] = c;
         self.len += 1;
     }
 
@@ -696,25 +1393,40 @@ impl VtIntermediates {
     #[inline(always)]
     pub fn clear(&mut self) {
         self.len = 0;
+        self.overrun = false;
     }
 
     /// Returns the intermediate characters as a slice of [`u8`] values.
     pub fn chars(&self) -> &[u8] {
-        let len = self.len();
-        &self.buf[..len]
+        &self.buf[..self.len]
     }
 
-    /// Returns the current number of intermediate characters.
+    /// Returns true if any pushes were ignored due to exceeding capacity.
     #[inline(always)]
-    pub fn len(&self) -> usize {
-        core::cmp::min(self.buf.len(), self.len as usize)
+    pub fn is_overrun(&self) -> bool {
+        self.overrun
+    }
+
+    /// Records that pushes were ignored, such as when restoring a snapshot of
+    /// a sequence that had already overrun.
+    #[inline(always)]
+    pub fn set_overrun(&mut self) {
+        self.overrun = true;
     }
 }
 
-impl core::fmt::Debug for VtIntermediates {
+impl<const N: usize> PartialEq for VtIntermediates<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.chars() == other.chars() && self.is_overrun() == other.is_overrun()
+    }
+}
+
+impl<const N: usize> Eq for VtIntermediates<N> {}
+
+impl<const N: usize> core::fmt::Debug for VtIntermediates<N> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_tuple("VtIntermediates")
-            .field(&&self.buf[..(self.len as usize)])
+            .field(&self.chars())
             .finish()
     }
 }
@@ -0,0 +1,405 @@
+/// A complete operating system command decoded into one of the commands
+/// commonly supported by xterm-compatible terminals.
+///
+/// Use [`OscCommand::parse`] with the full content of the command, such as
+/// `data` from `VtBufferedEvent::Osc` or the characters gathered
+/// from [`crate::VtEvent::OscChar`] events. Text fields are returned as raw
+/// bytes, which are usually but not necessarily valid UTF-8.
+///
+/// Commands that this parser does not recognize, or whose arguments are not
+/// in the expected form, are reported as [`OscCommand::Unknown`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OscCommand<'m> {
+    /// OSC 0: set both the icon name and the window title.
+    SetIconNameAndTitle(&'m [u8]),
+    /// OSC 1: set the icon name.
+    SetIconName(&'m [u8]),
+    /// OSC 2: set the window title.
+    SetTitle(&'m [u8]),
+    /// OSC 4: set or query one or more entries in the color palette.
+    PaletteColors(OscColorPairs<'m>),
+    /// OSC 10, 11, or 12: set or query the default foreground, default
+    /// background, or cursor color.
+    ///
+    /// xterm also accepts several specs separated by semicolons, each
+    /// applying to the next dynamic color in turn, such as `OSC 10;?;? ST`
+    /// to query both the foreground and the background. That form is
+    /// reported as [`OscCommand::Unknown`].
+    DynamicColor {
+        target: DynamicColor,
+        spec: OscColorSpec<'m>,
+    },
+    /// OSC 7: report the current working directory, as a `file:` URL.
+    WorkingDirectory(&'m [u8]),
+    /// OSC 8: begin a hyperlink to the given URI, or end the current one if
+    /// the URI is empty.
+    ///
+    /// `params` is a colon-separated list of `key=value` pairs, such as
+    /// `id=abc`.
+    Hyperlink { params: &'m [u8], uri: &'m [u8] },
+    /// OSC 52: set or query the content of a clipboard.
+    ///
+    /// `selection` lists the clipboards to act on, such as `c` for the
+    /// system clipboard, and is empty if the program did not specify any.
+    Clipboard {
+        selection: &'m [u8],
+        data: ClipboardData<'m>,
+    },
+    /// OSC 133: a shell integration mark.
+    ShellMark(ShellMark<'m>),
+    /// OSC 9, or OSC 777 with `notify`: show a desktop notification.
+    Notify {
+        title: Option<&'m [u8]>,
+        body: &'m [u8],
+    },
+    /// OSC 1337: one of the iTerm2 proprietary commands, such as
+    /// `CurrentDir=/home`, split at the first equals sign.
+    ITerm2 { key: &'m [u8], value: &'m [u8] },
+    /// Any command not covered by the other variants.
+    ///
+    /// `number` is `None` if the command did not start with a valid number,
+    /// in which case `args` is the entire content.
+    Unknown { number: Option<u16>, args: &'m [u8] },
+}
+
+impl<'m> OscCommand<'m> {
+    /// Decodes the given content of an operating system command, not
+    /// including its introducer or terminator.
+    pub fn parse(data: &'m [u8]) -> Self {
+        let (number, args) = split_field(data);
+        let Some(number) = parse_number(number) else {
+            return OscCommand::Unknown {
+                number: None,
+                args: data,
+            };
+        };
+        Self::parse_args(number, args.unwrap_or(b"")).unwrap_or(OscCommand::Unknown {
+            number: Some(number),
+            args: args.unwrap_or(b""),
+        })
+    }
+
+    fn parse_args(number: u16, args: &'m [u8]) -> Option<Self> {
+        let cmd = match number {
+            0 => OscCommand::SetIconNameAndTitle(args),
+            1 => OscCommand::SetIconName(args),
+            2 => OscCommand::SetTitle(args),
+            4 => OscCommand::PaletteColors(OscColorPairs { rest: Some(args) }),
+            7 => OscCommand::WorkingDirectory(args),
+            8 => {
+                let (params, uri) = split_field(args);
+                OscCommand::Hyperlink { params, uri: uri? }
+            }
+            9 => {
+                // ConEmu uses OSC 9 with a numeric subcommand for various
+                // other purposes, such as progress reporting.
+                if let (sub, Some(_)) = split_field(args) {
+                    if parse_number(sub).is_some() {
+                        return None;
+                    }
+                }
+                OscCommand::Notify {
+                    title: None,
+                    body: args,
+                }
+            }
+            10..=12 => {
+                if args.contains(&b';') {
+                    return None;
+                }
+                OscCommand::DynamicColor {
+                    target: match number {
+                        10 => DynamicColor::Foreground,
+                        11 => DynamicColor::Background,
+                        _ => DynamicColor::Cursor,
+                    },
+                    spec: OscColorSpec::new(args),
+                }
+            }
+            52 => {
+                let (selection, data) = split_field(args);
+                let data = data?;
+                OscCommand::Clipboard {
+                    selection,
+                    data: match data {
+                        b"?" => ClipboardData::Query,
+                        _ => ClipboardData::Set(Base64Data(data)),
+                    },
+                }
+            }
+            133 => OscCommand::ShellMark(ShellMark::parse(args)),
+            777 => {
+                let (sub, rest) = split_field(args);
+                if sub != b"notify" {
+                    return None;
+                }
+                let (title, body) = split_field(rest?);
+                OscCommand::Notify {
+                    title: Some(title),
+                    body: body.unwrap_or(b""),
+                }
+            }
+            1337 => {
+                let mut parts = args.splitn(2, |b| *b == b'=');
+                let key = parts.next()?;
+                OscCommand::ITerm2 {
+                    key,
+                    value: parts.next().unwrap_or(b""),
+                }
+            }
+            _ => return None,
+        };
+        Some(cmd)
+    }
+}
+
+/// The color that [`OscCommand::DynamicColor`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicColor {
+    /// OSC 10: the default text color.
+    Foreground,
+    /// OSC 11: the default background color.
+    Background,
+    /// OSC 12: the color of the text cursor.
+    Cursor,
+}
+
+/// Either a request to report a color, or a new color to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OscColorSpec<'m> {
+    /// The program asked the terminal to report the current color.
+    Query,
+    /// The program asked the terminal to use the given color, which is in
+    /// one of the forms accepted by `XParseColor`, such as `rgb:ff/80/00`,
+    /// `#ff8000`, or a color name.
+    Set(&'m [u8]),
+}
+
+impl<'m> OscColorSpec<'m> {
+    fn new(spec: &'m [u8]) -> Self {
+        match spec {
+            b"?" => OscColorSpec::Query,
+            _ => OscColorSpec::Set(spec),
+        }
+    }
+
+    /// Returns the 8-bit red, green and blue components of the color if it
+    /// is in either the `rgb:` or `#` form, or `None` otherwise.
+    pub fn rgb(&self) -> Option<(u8, u8, u8)> {
+        let spec = match self {
+            OscColorSpec::Query => return None,
+            OscColorSpec::Set(spec) => *spec,
+        };
+        if let Some(spec) = spec.strip_prefix(b"rgb:") {
+            // Each component has 1 to 4 hex digits, scaled to the full range.
+            let mut parts = spec.split(|b| *b == b'/');
+            let mut component = || -> Option<u8> {
+                let digits = parts.next()?;
+                let v = parse_hex(digits)?;
+                let max = (1_u32 << (4 * digits.len())) - 1;
+                Some((v * 255 / max) as u8)
+            };
+            let ret = (component()?, component()?, component()?);
+            return parts.next().is_none().then_some(ret);
+        }
+        if let Some(spec) = spec.strip_prefix(b"#") {
+            // Each component has the same number of digits, of which only
+            // the most significant are used.
+            if spec.is_empty() || spec.len() % 3 != 0 || spec.len() > 12 {
+                return None;
+            }
+            let n = spec.len() / 3;
+            let component = |idx: usize| -> Option<u8> {
+                let v = parse_hex(&spec[idx * n..(idx + 1) * n])?;
+                Some(match n {
+                    1 => v << 4,
+                    _ => v >> (4 * (n - 2)),
+                } as u8)
+            };
+            return Some((component(0)?, component(1)?, component(2)?));
+        }
+        None
+    }
+}
+
+/// Iterator over the index and color pairs in an [`OscCommand::PaletteColors`].
+///
+/// The iterator ends early if it encounters a pair that is not valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OscColorPairs<'m> {
+    rest: Option<&'m [u8]>,
+}
+
+impl<'m> Iterator for OscColorPairs<'m> {
+    type Item = (u16, OscColorSpec<'m>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (idx, rest) = split_field(self.rest.take()?);
+        let (spec, rest) = split_field(rest?);
+        let idx = parse_number(idx)?;
+        self.rest = rest;
+        Some((idx, OscColorSpec::new(spec)))
+    }
+}
+
+impl<'m> core::iter::FusedIterator for OscColorPairs<'m> {}
+
+/// The data in an [`OscCommand::Clipboard`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardData<'m> {
+    /// The program asked the terminal to report the clipboard content.
+    Query,
+    /// The program asked the terminal to replace the clipboard content.
+    Set(Base64Data<'m>),
+}
+
+/// Base64-encoded data, which can be decoded without allocating by iterating
+/// over [`Base64Data::bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Base64Data<'m>(&'m [u8]);
+
+impl<'m> Base64Data<'m> {
+    /// Returns the data in its original encoded form.
+    pub fn encoded(&self) -> &'m [u8] {
+        self.0
+    }
+
+    /// Returns true if the data is entirely valid base64, with or without
+    /// padding.
+    ///
+    /// Padding is only accepted at the end, and only if it brings the
+    /// length to a multiple of four. Unpadded data must not have a length
+    /// that leaves a single character in its last group, since that cannot
+    /// encode a whole byte.
+    pub fn is_valid(&self) -> bool {
+        let end = self
+            .0
+            .iter()
+            .position(|b| *b == b'=')
+            .unwrap_or(self.0.len());
+        let (data, padding) = self.0.split_at(end);
+        let valid_len = match padding.len() {
+            0 => data.len() % 4 != 1,
+            1 => data.len() % 4 == 3,
+            2 => data.len() % 4 == 2,
+            _ => false,
+        };
+        valid_len
+            && data.iter().all(|b| base64_value(*b).is_some())
+            && padding.iter().all(|b| *b == b'=')
+    }
+
+    /// Returns an iterator over the decoded bytes.
+    ///
+    /// Decoding stops at the first padding or invalid character, so use
+    /// [`Self::is_valid`] first to reject malformed data.
+    pub fn bytes(&self) -> Base64Bytes<'m> {
+        Base64Bytes {
+            input: self.0,
+            acc: 0,
+            bits: 0,
+        }
+    }
+}
+
+/// Iterator over the decoded bytes of a [`Base64Data`].
+#[derive(Debug, Clone)]
+pub struct Base64Bytes<'m> {
+    input: &'m [u8],
+    acc: u32,
+    bits: u8,
+}
+
+impl<'m> Iterator for Base64Bytes<'m> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        while self.bits < 8 {
+            let (first, rest) = self.input.split_first()?;
+            let v = base64_value(*first)?;
+            self.input = rest;
+            self.acc = ((self.acc << 6) | v as u32) & 0xffff;
+            self.bits += 6;
+        }
+        self.bits -= 8;
+        Some((self.acc >> self.bits) as u8)
+    }
+}
+
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// A shell integration mark from [`OscCommand::ShellMark`], as used by
+/// FinalTerm and its successors to delimit prompts and command output.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellMark<'m> {
+    /// `A`: the shell is about to print a prompt.
+    PromptStart,
+    /// `B`: the prompt has ended and the user is typing a command.
+    CommandStart,
+    /// `C`: the command has been entered and is about to run.
+    CommandExecuted,
+    /// `D`: the command has finished, with the given exit status if known.
+    CommandFinished { exit_code: Option<u32> },
+    /// Any other mark, given as its full content.
+    Other(&'m [u8]),
+}
+
+impl<'m> ShellMark<'m> {
+    fn parse(args: &'m [u8]) -> Self {
+        let (kind, rest) = split_field(args);
+        match kind {
+            b"A" => ShellMark::PromptStart,
+            b"B" => ShellMark::CommandStart,
+            b"C" => ShellMark::CommandExecuted,
+            b"D" => ShellMark::CommandFinished {
+                exit_code: rest
+                    .map(|rest| split_field(rest).0)
+                    .and_then(|code| core::str::from_utf8(code).ok())
+                    .and_then(|code| code.parse().ok()),
+            },
+            _ => ShellMark::Other(args),
+        }
+    }
+}
+
+/// Splits the given data at the first semicolon, returning `None` as the
+/// second element if there isn't one.
+fn split_field(data: &[u8]) -> (&[u8], Option<&[u8]>) {
+    match data.iter().position(|b| *b == b';') {
+        Some(idx) => (&data[..idx], Some(&data[idx + 1..])),
+        None => (data, None),
+    }
+}
+
+fn parse_number(digits: &[u8]) -> Option<u16> {
+    if digits.is_empty() {
+        return None;
+    }
+    digits.iter().try_fold(0_u16, |acc, b| {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        acc.checked_mul(10)?.checked_add((b - b'0') as u16)
+    })
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 4 {
+        return None;
+    }
+    digits.iter().try_fold(0_u32, |acc, b| {
+        let v = (*b as char).to_digit(16)?;
+        Some((acc << 4) | v)
+    })
+}
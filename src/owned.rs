@@ -0,0 +1,292 @@
+use u8char::u8char;
+
+use crate::params::ParamsBuf;
+use crate::{VtErrorKind, VtEvent, VtParams, VtState};
+
+/// An owned counterpart of [`VtEvent`], which can be retained after more
+/// input is written to the machine, such as to queue it or send it to
+/// another thread.
+///
+/// Parameters and intermediate characters are stored inline in arrays with
+/// capacities given by the type parameters, which default to the same
+/// capacities as [`crate::VtMachine::new`], so this type never allocates.
+/// Use [`VtEvent::into_owned`] for the default capacities, or
+/// [`VtEventOwned::from_event`] for others.
+///
+/// Use [`VtEventOwned::as_event`] to borrow it as a [`VtEvent`] again, such
+/// as to pass it to [`VtEvent::dispatch_to`] or [`VtEvent::csi_command`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtEventOwned<const PARAMS: usize = 16, const INTERMEDIATES: usize = 2> {
+    /// The owned form of [`VtEvent::Print`].
+    Print(u8char),
+    /// The owned form of [`VtEvent::PrintEnd`].
+    PrintEnd,
+    /// The owned form of [`VtEvent::ExecuteCtrl`].
+    ExecuteCtrl(u8),
+    /// The owned form of [`VtEvent::DispatchCsi`].
+    DispatchCsi {
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParamsOwned<PARAMS>,
+        intermediates: VtIntermediatesOwned<INTERMEDIATES>,
+        truncated: bool,
+    },
+    /// The owned form of [`VtEvent::DispatchEsc`].
+    ///
+    /// `truncated` is set if the sequence had more intermediate characters
+    /// than this type has capacity for, in which case `intermediates` does
+    /// not identify the sequence correctly.
+    DispatchEsc {
+        cmd: u8,
+        intermediates: VtIntermediatesOwned<INTERMEDIATES>,
+        truncated: bool,
+    },
+    /// The owned form of [`VtEvent::DcsStart`].
+    DcsStart {
+        cmd: u8,
+        private_marker: Option<u8>,
+        params: VtParamsOwned<PARAMS>,
+        intermediates: VtIntermediatesOwned<INTERMEDIATES>,
+        truncated: bool,
+    },
+    /// The owned form of [`VtEvent::DcsChar`].
+    DcsChar(u8char),
+    /// The owned form of [`VtEvent::DcsEnd`].
+    DcsEnd(u8),
+    /// The owned form of [`VtEvent::OscStart`].
+    OscStart(u8),
+    /// The owned form of [`VtEvent::OscChar`].
+    OscChar(u8char),
+    /// The owned form of [`VtEvent::OscEnd`].
+    OscEnd(u8),
+    /// The owned form of [`VtEvent::SosPmApcStart`].
+    SosPmApcStart(u8),
+    /// The owned form of [`VtEvent::SosPmApcChar`].
+    SosPmApcChar(u8char),
+    /// The owned form of [`VtEvent::SosPmApcEnd`].
+    SosPmApcEnd(u8),
+    /// The owned form of [`VtEvent::Error`].
+    Error {
+        c: u8char,
+        kind: VtErrorKind,
+        state: VtState,
+    },
+}
+
+impl<const PARAMS: usize, const INTERMEDIATES: usize> VtEventOwned<PARAMS, INTERMEDIATES> {
+    /// Copies the given event into an owned event.
+    ///
+    /// If the event has more parameters or intermediate characters than this
+    /// type has capacity for then the extras are discarded and `truncated`
+    /// is set. Use capacities at least as large as those of the machine that
+    /// produced the event to avoid that.
+    pub fn from_event(event: VtEvent<'_>) -> Self {
+        match event {
+            VtEvent::Print(c) => VtEventOwned::Print(c),
+            VtEvent::PrintEnd => VtEventOwned::PrintEnd,
+            VtEvent::ExecuteCtrl(c) => VtEventOwned::ExecuteCtrl(c),
+            VtEvent::DispatchCsi {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            } => {
+                let params = VtParamsOwned::new(params);
+                let intermediates = VtIntermediatesOwned::new(intermediates);
+                VtEventOwned::DispatchCsi {
+                    cmd,
+                    private_marker,
+                    truncated: truncated || params.0.is_truncated() || intermediates.truncated,
+                    params,
+                    intermediates,
+                }
+            }
+            VtEvent::DispatchEsc { cmd, intermediates } => {
+                let intermediates = VtIntermediatesOwned::new(intermediates);
+                VtEventOwned::DispatchEsc {
+                    cmd,
+                    truncated: intermediates.truncated,
+                    intermediates,
+                }
+            }
+            VtEvent::DcsStart {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            } => {
+                let params = VtParamsOwned::new(params);
+                let intermediates = VtIntermediatesOwned::new(intermediates);
+                VtEventOwned::DcsStart {
+                    cmd,
+                    private_marker,
+                    truncated: truncated || params.0.is_truncated() || intermediates.truncated,
+                    params,
+                    intermediates,
+                }
+            }
+            VtEvent::DcsChar(c) => VtEventOwned::DcsChar(c),
+            VtEvent::DcsEnd(c) => VtEventOwned::DcsEnd(c),
+            VtEvent::OscStart(c) => VtEventOwned::OscStart(c),
+            VtEvent::OscChar(c) => VtEventOwned::OscChar(c),
+            VtEvent::OscEnd(c) => VtEventOwned::OscEnd(c),
+            VtEvent::SosPmApcStart(c) => VtEventOwned::SosPmApcStart(c),
+            VtEvent::SosPmApcChar(c) => VtEventOwned::SosPmApcChar(c),
+            VtEvent::SosPmApcEnd(c) => VtEventOwned::SosPmApcEnd(c),
+            VtEvent::Error { c, kind, state } => VtEventOwned::Error { c, kind, state },
+        }
+    }
+
+    /// Returns a [`VtEvent`] borrowing from this owned event.
+    ///
+    /// [`VtEvent::DispatchEsc`] has no way to report truncation, so check
+    /// `truncated` before using the result of a truncated
+    /// [`VtEventOwned::DispatchEsc`].
+    pub fn as_event(&self) -> VtEvent<'_> {
+        match self {
+            VtEventOwned::Print(c) => VtEvent::Print(*c),
+            VtEventOwned::PrintEnd => VtEvent::PrintEnd,
+            VtEventOwned::ExecuteCtrl(c) => VtEvent::ExecuteCtrl(*c),
+            VtEventOwned::DispatchCsi {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            } => VtEvent::DispatchCsi {
+                cmd: *cmd,
+                private_marker: *private_marker,
+                params: params.params(),
+                intermediates: intermediates.chars(),
+                truncated: *truncated,
+            },
+            VtEventOwned::DispatchEsc {
+                cmd, intermediates, ..
+            } => VtEvent::DispatchEsc {
+                cmd: *cmd,
+                intermediates: intermediates.chars(),
+            },
+            VtEventOwned::DcsStart {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            } => VtEvent::DcsStart {
+                cmd: *cmd,
+                private_marker: *private_marker,
+                params: params.params(),
+                intermediates: intermediates.chars(),
+                truncated: *truncated,
+            },
+            VtEventOwned::DcsChar(c) => VtEvent::DcsChar(*c),
+            VtEventOwned::DcsEnd(c) => VtEvent::DcsEnd(*c),
+            VtEventOwned::OscStart(c) => VtEvent::OscStart(*c),
+            VtEventOwned::OscChar(c) => VtEvent::OscChar(*c),
+            VtEventOwned::OscEnd(c) => VtEvent::OscEnd(*c),
+            VtEventOwned::SosPmApcStart(c) => VtEvent::SosPmApcStart(*c),
+            VtEventOwned::SosPmApcChar(c) => VtEvent::SosPmApcChar(*c),
+            VtEventOwned::SosPmApcEnd(c) => VtEvent::SosPmApcEnd(*c),
+            VtEventOwned::Error { c, kind, state } => VtEvent::Error {
+                c: *c,
+                kind: *kind,
+                state: *state,
+            },
+        }
+    }
+}
+
+impl<'m> VtEvent<'m> {
+    /// Copies the event into a [`VtEventOwned`] with the default capacities,
+    /// which can hold any event from a machine created by
+    /// [`crate::VtMachine::new`].
+    ///
+    /// This is the same as [`VtEventOwned::from_event`], which also supports
+    /// other capacities.
+    ///
+    /// It is not named `to_owned` because [`VtEvent`] is [`Clone`], so that
+    /// name would be shadowed by `ToOwned::to_owned` when called on a
+    /// `&VtEvent`, which would silently return another [`VtEvent`].
+    #[doc(alias = "to_owned")]
+    pub fn into_owned(self) -> VtEventOwned {
+        VtEventOwned::from_event(self)
+    }
+}
+
+impl<'m, const PARAMS: usize, const INTERMEDIATES: usize> From<VtEvent<'m>>
+    for VtEventOwned<PARAMS, INTERMEDIATES>
+{
+    fn from(event: VtEvent<'m>) -> Self {
+        Self::from_event(event)
+    }
+}
+
+/// Parameters stored inline in a [`VtEventOwned`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct VtParamsOwned<const N: usize>(ParamsBuf<N>);
+
+impl<const N: usize> VtParamsOwned<N> {
+    fn new(params: VtParams<'_>) -> Self {
+        let mut buf = ParamsBuf::new();
+        for (idx, v) in params.values().iter().enumerate() {
+            buf.push(*v, params.flag(idx));
+        }
+        Self(buf)
+    }
+
+    /// Returns the parameters as a [`VtParams`].
+    #[inline(always)]
+    pub fn params(&self) -> VtParams<'_> {
+        self.0.params()
+    }
+}
+
+impl<const N: usize> core::fmt::Debug for VtParamsOwned<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.params(), f)
+    }
+}
+
+/// Intermediate characters stored inline in a [`VtEventOwned`].
+#[derive(Clone, Copy)]
+pub struct VtIntermediatesOwned<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> VtIntermediatesOwned<N> {
+    fn new(chars: &[u8]) -> Self {
+        let len = chars.len().min(N);
+        let mut buf = [0; N];
+        buf[..len].copy_from_slice(&chars[..len]);
+        Self {
+            buf,
+            len,
+            truncated: len < chars.len(),
+        }
+    }
+
+    /// Returns the intermediate characters as a slice of [`u8`] values.
+    #[inline(always)]
+    pub fn chars(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<const N: usize> PartialEq for VtIntermediatesOwned<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.chars() == other.chars()
+    }
+}
+
+impl<const N: usize> Eq for VtIntermediatesOwned<N> {}
+
+impl<const N: usize> core::fmt::Debug for VtIntermediatesOwned<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.chars(), f)
+    }
+}
@@ -0,0 +1,332 @@
+use u8char::u8char;
+
+/// Zero or more `u16` values given as parameters in a control sequence, or
+/// similar, as reported by [`crate::VtEvent::DispatchCsi`] and
+/// [`crate::VtEvent::DcsStart`].
+///
+/// Each parameter can optionally be followed by some colon-separated
+/// sub-parameters, such as in the SGR sequence `ESC[38:2::255:0:0m`. The
+/// parameter and its sub-parameters together are called a _group_. Use
+/// [`VtParams::groups`] to visit each group in turn, or [`VtParams::values`]
+/// to get all of the values as a flat slice.
+///
+/// A parameter can also be omitted, as in `ESC[;5H`, which many commands
+/// treat differently than an explicit zero. Omitted parameters appear as zero
+/// in [`VtParams::values`] and [`VtParams::groups`], but [`VtParams::get`]
+/// and [`VtParams::param_or`] distinguish them.
+///
+/// Values too large to fit in a `u16` saturate at [`u16::MAX`], and
+/// [`VtParams::has_overflow`] reports whether that happened so that callers
+/// can reject the sequence rather than act on a clamped value.
+#[derive(Clone, Copy)]
+pub struct VtParams<'m> {
+    values: &'m [u16],
+    flags: &'m [u8],
+}
+
+impl<'m> VtParams<'m> {
+    /// Constructs a [`VtParams`] describing the given values, each of which
+    /// is a separate parameter without any sub-parameters.
+    pub const fn from_values(values: &'m [u16]) -> Self {
+        Self { values, flags: &[] }
+    }
+
+    pub(crate) const fn from_parts(values: &'m [u16], flags: &'m [u8]) -> Self {
+        Self { values, flags }
+    }
+
+    /// Returns all of the parameter and sub-parameter values as a flat slice.
+    #[inline(always)]
+    pub fn values(&self) -> &'m [u16] {
+        self.values
+    }
+
+    /// Returns an iterator over the parameter groups, each of which is a
+    /// slice whose first element is the parameter itself and whose subsequent
+    /// elements are its sub-parameters.
+    #[inline(always)]
+    pub fn groups(&self) -> VtParamGroups<'m> {
+        VtParamGroups {
+            params: *self,
+            pos: 0,
+        }
+    }
+
+    /// Returns the number of parameters, not counting sub-parameters.
+    pub fn len(&self) -> usize {
+        self.groups().count()
+    }
+
+    /// Returns true if there are no parameters at all.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the value of the parameter at the given index, not counting
+    /// sub-parameters, or `None` if that parameter was omitted or is beyond
+    /// the end of the list.
+    pub fn get(&self, idx: usize) -> Option<u16> {
+        let value_idx = self.group_start(idx)?;
+        if self.is_default(value_idx) {
+            return None;
+        }
+        Some(self.values[value_idx])
+    }
+
+    /// Returns the value of the parameter at the given index, not counting
+    /// sub-parameters, or the given default value if that parameter was
+    /// omitted or is beyond the end of the list.
+    ///
+    /// For example, a handler for the CUP sequence `ESC[5H` might use
+    /// `params.param_or(1, 1)` to find the column, which defaults to 1.
+    #[inline]
+    pub fn param_or(&self, idx: usize, default: u16) -> u16 {
+        self.get(idx).unwrap_or(default)
+    }
+
+    /// Returns an iterator over the parameter values, not counting
+    /// sub-parameters, with `None` representing an omitted parameter.
+    pub fn iter(&self) -> impl Iterator<Item = Option<u16>> + 'm {
+        let params = *self;
+        (0..self.values.len())
+            .filter(move |idx| !params.is_subparam(*idx))
+            .map(move |idx| (!params.is_default(idx)).then(|| params.values[idx]))
+    }
+
+    /// Returns true if the value at the given index in [`Self::values`] was
+    /// omitted, in which case its value is zero.
+    #[inline(always)]
+    pub fn is_default(&self, idx: usize) -> bool {
+        self.flag(idx) & DEFAULT != 0
+    }
+
+    /// Returns true if the value at the given index in [`Self::values`] was
+    /// separated from the one before it by a colon, making it a sub-parameter.
+    #[inline(always)]
+    pub fn is_subparam(&self, idx: usize) -> bool {
+        self.flag(idx) & SUBPARAM != 0
+    }
+
+    /// Returns true if the value at the given index in [`Self::values`] was
+    /// too large to represent, in which case its value is [`u16::MAX`].
+    #[inline(always)]
+    pub fn is_overflowed(&self, idx: usize) -> bool {
+        self.flag(idx) & OVERFLOW != 0
+    }
+
+    /// Returns true if any of the values were too large to represent.
+    pub fn has_overflow(&self) -> bool {
+        (0..self.values.len()).any(|idx| self.is_overflowed(idx))
+    }
+
+    /// Returns the index into [`Self::values`] where the parameter at the
+    /// given index begins.
+    fn group_start(&self, idx: usize) -> Option<usize> {
+        (0..self.values.len())
+            .filter(|idx| !self.is_subparam(*idx))
+            .nth(idx)
+    }
+
+    #[inline(always)]
+    pub(crate) fn flag(&self, idx: usize) -> u8 {
+        // A missing flag is the same as no flags at all, which allows
+        // Self::from_values to borrow an empty flags slice.
+        self.flags.get(idx).copied().unwrap_or(0)
+    }
+}
+
+impl<'m> PartialEq for VtParams<'m> {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+            && (0..self.values.len()).all(|idx| self.flag(idx) == other.flag(idx))
+    }
+}
+
+impl<'m> Eq for VtParams<'m> {}
+
+impl<'m> core::fmt::Debug for VtParams<'m> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        struct Group<'a>(&'a VtParams<'a>, usize, &'a [u16]);
+
+        impl<'a> core::fmt::Debug for Group<'a> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                let (params, start) = (self.0, self.1);
+                for (idx, v) in self.2.iter().enumerate() {
+                    if idx != 0 {
+                        f.write_str(":")?;
+                    }
+                    if params.is_default(start + idx) {
+                        f.write_str("_")?;
+                    } else if params.is_overflowed(start + idx) {
+                        f.write_str(">")?;
+                        core::fmt::Debug::fmt(v, f)?;
+                    } else {
+                        core::fmt::Debug::fmt(v, f)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        let mut start = 0;
+        f.debug_list()
+            .entries(self.groups().map(|group| {
+                let ret = Group(self, start, group);
+                start += group.len();
+                ret
+            }))
+            .finish()
+    }
+}
+
+/// Iterator over the parameter groups in a [`VtParams`], returned by
+/// [`VtParams::groups`].
+#[derive(Clone)]
+pub struct VtParamGroups<'m> {
+    params: VtParams<'m>,
+    pos: usize,
+}
+
+impl<'m> Iterator for VtParamGroups<'m> {
+    type Item = &'m [u16];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let values = self.params.values;
+        let start = self.pos;
+        if start >= values.len() {
+            return None;
+        }
+        let mut end = start + 1;
+        while end < values.len() && self.params.is_subparam(end) {
+            end += 1;
+        }
+        self.pos = end;
+        Some(&values[start..end])
+    }
+}
+
+/// Flag marking a value that was introduced by a colon separator.
+const SUBPARAM: u8 = 0b0000_0001;
+/// Flag marking a value that has not had any digits written to it yet.
+const DEFAULT: u8 = 0b0000_0010;
+/// Flag marking a value that saturated because it was too large.
+const OVERFLOW: u8 = 0b0000_0100;
+
+/// Returns true if the given value has only the flags defined above, which
+/// is the case for all of the flags returned by [`VtParams::flag`].
+pub(crate) fn is_valid_flags(flags: u8) -> bool {
+    flags & !(SUBPARAM | DEFAULT | OVERFLOW) == 0
+}
+
+/// Storage for the parameters of the sequence currently being parsed,
+/// which [`crate::VtMachine`] exposes to callers as [`VtParams`].
+#[derive(Clone, Copy)]
+pub(crate) struct ParamsBuf<const N: usize> {
+    buf: [u16; N],
+    flags: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> ParamsBuf<N> {
+    /// Constructs a new zero-length [`ParamsBuf`].
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            flags: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Attempts to push a new value.
+    ///
+    /// A [`ParamsBuf`] has a capacity of `N` items, and so any pushes after
+    /// that capacity has been reached are ignored, but recorded so that
+    /// [`Self::is_truncated`] will return true.
+    pub fn push(&mut self, v: u16, flags: u8) {
+        if self.len == self.buf.len() {
+            self.truncated = true;
+            return;
+        }
+        self.buf[self.len] = v;
+        self.flags[self.len] = flags;
+        self.len += 1;
+    }
+
+    pub fn push_csi_char(&mut self, c: u8char) {
+        if self.len == 0 && !self.truncated {
+            self.push(0, DEFAULT); // start our first param
+        }
+        match c.first_byte() {
+            // Argument separator, so we start a new param.
+            b';' => self.push(0, DEFAULT),
+            // Sub-parameter separator, so we start a new value in the
+            // current group.
+            b':' => self.push(0, SUBPARAM | DEFAULT),
+            _ if self.truncated => {
+                // Digits belong to a value we already discarded.
+            }
+            _ => {
+                // The character must be a digit, then
+                let idx = self.len - 1;
+                self.flags[idx] &= !DEFAULT;
+                let current = &mut self.buf[idx];
+                let digit = (c.to_char() as u16) - ('0' as u16);
+                match current.checked_mul(10).and_then(|v| v.checked_add(digit)) {
+                    Some(v) => *current = v,
+                    None => {
+                        // Hostile or buggy programs can send arbitrarily-long
+                        // digit sequences, so we saturate and remember that
+                        // the value is not accurate.
+                        *current = u16::MAX;
+                        self.flags[idx] |= OVERFLOW;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Discard all of the parameters, causing the object to then have length zero.
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+
+    /// Returns true if any pushes were ignored due to exceeding capacity.
+    #[inline(always)]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Records that pushes were ignored, such as when restoring a snapshot of
+    /// a buffer that was already truncated.
+    #[inline(always)]
+    pub fn set_truncated(&mut self) {
+        self.truncated = true;
+    }
+
+    /// Returns the parameters as a [`VtParams`] borrowing from this buffer.
+    #[inline(always)]
+    pub fn params(&self) -> VtParams<'_> {
+        VtParams::from_parts(&self.buf[..self.len], &self.flags[..self.len])
+    }
+}
+
+impl<const N: usize> PartialEq for ParamsBuf<N> {
+    fn eq(&self, other: &Self) -> bool {
+        // Values beyond the current length are leftovers from earlier
+        // sequences, so they don't participate.
+        self.params() == other.params() && self.truncated == other.truncated
+    }
+}
+
+impl<const N: usize> Eq for ParamsBuf<N> {}
+
+impl<const N: usize> core::fmt::Debug for ParamsBuf<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("ParamsBuf").field(&self.params()).finish()
+    }
+}
@@ -0,0 +1,323 @@
+use crate::{VtParamGroups, VtParams};
+
+/// A single attribute from the parameters of an SGR (Select Graphic
+/// Rendition) control sequence, `ESC[...m`, as produced by [`SgrAttributes`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SgrAttribute<'m> {
+    /// `0`, or no parameters at all: return all attributes to their
+    /// defaults.
+    Reset,
+    /// `1`: increased intensity.
+    Bold,
+    /// `2`: decreased intensity.
+    Faint,
+    /// `3`: italic text.
+    Italic,
+    /// `4`, or `4:n` for the styles other than a single line, or `21` for a
+    /// double line.
+    Underline(UnderlineStyle),
+    /// `5`: slow blinking.
+    Blink,
+    /// `6`: rapid blinking.
+    RapidBlink,
+    /// `7`: swap the foreground and background colors.
+    Inverse,
+    /// `8`: hide the text, while still taking up space.
+    Hidden,
+    /// `9`: draw a line through the middle of the text.
+    Strikethrough,
+    /// `53`: draw a line above the text.
+    Overline,
+    /// `22`: turn off both [`Self::Bold`] and [`Self::Faint`].
+    NormalIntensity,
+    /// `23`: turn off [`Self::Italic`].
+    NotItalic,
+    /// `24` or `4:0`: turn off [`Self::Underline`].
+    NotUnderlined,
+    /// `25`: turn off both [`Self::Blink`] and [`Self::RapidBlink`].
+    NotBlinking,
+    /// `27`: turn off [`Self::Inverse`].
+    NotInverse,
+    /// `28`: turn off [`Self::Hidden`].
+    NotHidden,
+    /// `29`: turn off [`Self::Strikethrough`].
+    NotStrikethrough,
+    /// `55`: turn off [`Self::Overline`].
+    NotOverline,
+    /// `30` through `37`, `90` through `97`, or `38` followed by an extended
+    /// color: set the text color.
+    Foreground(SgrColor),
+    /// `39`: use the terminal's default text color.
+    DefaultForeground,
+    /// `40` through `47`, `100` through `107`, or `48` followed by an
+    /// extended color: set the background color.
+    Background(SgrColor),
+    /// `49`: use the terminal's default background color.
+    DefaultBackground,
+    /// `58` followed by an extended color: set the color of underlines,
+    /// which otherwise match the text color.
+    UnderlineColor(SgrColor),
+    /// `59`: draw underlines in the text color again.
+    DefaultUnderlineColor,
+    /// An attribute this parser does not recognize, or one whose
+    /// sub-parameters are invalid, given as the values it was made from.
+    Unknown(&'m [u16]),
+}
+
+/// A color selected by an [`SgrAttribute`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SgrColor {
+    /// One of the 16 standard colors, where 0 through 7 are the normal
+    /// colors and 8 through 15 are their bright variants.
+    Ansi(u8),
+    /// An index into the terminal's 256-color palette, selected using
+    /// `38;5;n` or similar.
+    Indexed(u8),
+    /// A direct color, selected using `38;2;r;g;b` or similar.
+    Rgb(u8, u8, u8),
+}
+
+/// The style of line requested by [`SgrAttribute::Underline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnderlineStyle {
+    /// `4` or `4:1`: a single straight line.
+    Single,
+    /// `4:2` or `21`: two straight lines.
+    Double,
+    /// `4:3`: a wavy line.
+    Curly,
+    /// `4:4`: a dotted line.
+    Dotted,
+    /// `4:5`: a dashed line.
+    Dashed,
+}
+
+/// Iterator over the attributes described by the parameters of an SGR
+/// control sequence.
+///
+/// Extended colors are accepted both in the colon-separated sub-parameter
+/// form, like `38:2::255:0:0`, and in the older semicolon-separated form,
+/// like `38;2;255;0;0`.
+#[derive(Clone)]
+pub struct SgrAttributes<'m> {
+    values: &'m [u16],
+    groups: VtParamGroups<'m>,
+    pos: usize,
+    empty: bool,
+}
+
+impl<'m> SgrAttributes<'m> {
+    /// Returns an iterator over the attributes in the given parameters,
+    /// such as those from [`crate::CsiCommand::SelectGraphicRendition`].
+    pub fn new(params: VtParams<'m>) -> Self {
+        Self {
+            values: params.values(),
+            groups: params.groups(),
+            pos: 0,
+            empty: params.is_empty(),
+        }
+    }
+
+    fn next_group(&mut self) -> Option<&'m [u16]> {
+        let group = self.groups.next()?;
+        self.pos += group.len();
+        Some(group)
+    }
+
+    /// Parses the color selected by an extended color attribute such as 38,
+    /// consuming further groups if it uses the semicolon-separated form.
+    fn extended_color(&mut self, group: &'m [u16]) -> Option<SgrColor> {
+        if group.len() > 1 {
+            return match group[1..] {
+                [5, idx] => Some(SgrColor::Indexed(u8::try_from(idx).ok()?)),
+                // The standard form includes a color space identifier
+                // before the components, but some programs omit it.
+                [2, _, r, g, b] | [2, r, g, b] => rgb(r, g, b),
+                _ => None,
+            };
+        }
+        match self.next_group()? {
+            [5] => {
+                let idx = self.next_group()?;
+                Some(SgrColor::Indexed(u8::try_from(*idx.first()?).ok()?))
+            }
+            [2] => {
+                let r = *self.next_group()?.first()?;
+                let g = *self.next_group()?.first()?;
+                let b = *self.next_group()?.first()?;
+                rgb(r, g, b)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn rgb(r: u16, g: u16, b: u16) -> Option<SgrColor> {
+    Some(SgrColor::Rgb(
+        u8::try_from(r).ok()?,
+        u8::try_from(g).ok()?,
+        u8::try_from(b).ok()?,
+    ))
+}
+
+impl<'m> Iterator for SgrAttributes<'m> {
+    type Item = SgrAttribute<'m>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.empty {
+            // `ESC[m` is the same as `ESC[0m`.
+            self.empty = false;
+            return Some(SgrAttribute::Reset);
+        }
+        let start = self.pos;
+        let group = self.next_group()?;
+        let attr = match group[0] {
+            0 => SgrAttribute::Reset,
+            1 => SgrAttribute::Bold,
+            2 => SgrAttribute::Faint,
+            3 => SgrAttribute::Italic,
+            4 => match group.get(1) {
+                None | Some(1) => SgrAttribute::Underline(UnderlineStyle::Single),
+                Some(0) => SgrAttribute::NotUnderlined,
+                Some(2) => SgrAttribute::Underline(UnderlineStyle::Double),
+                Some(3) => SgrAttribute::Underline(UnderlineStyle::Curly),
+                Some(4) => SgrAttribute::Underline(UnderlineStyle::Dotted),
+                Some(5) => SgrAttribute::Underline(UnderlineStyle::Dashed),
+                Some(_) => SgrAttribute::Unknown(group),
+            },
+            5 => SgrAttribute::Blink,
+            6 => SgrAttribute::RapidBlink,
+            7 => SgrAttribute::Inverse,
+            8 => SgrAttribute::Hidden,
+            9 => SgrAttribute::Strikethrough,
+            21 => SgrAttribute::Underline(UnderlineStyle::Double),
+            22 => SgrAttribute::NormalIntensity,
+            23 => SgrAttribute::NotItalic,
+            24 => SgrAttribute::NotUnderlined,
+            25 => SgrAttribute::NotBlinking,
+            27 => SgrAttribute::NotInverse,
+            28 => SgrAttribute::NotHidden,
+            29 => SgrAttribute::NotStrikethrough,
+            n @ 30..=37 => SgrAttribute::Foreground(SgrColor::Ansi((n - 30) as u8)),
+            39 => SgrAttribute::DefaultForeground,
+            n @ 40..=47 => SgrAttribute::Background(SgrColor::Ansi((n - 40) as u8)),
+            49 => SgrAttribute::DefaultBackground,
+            53 => SgrAttribute::Overline,
+            55 => SgrAttribute::NotOverline,
+            59 => SgrAttribute::DefaultUnderlineColor,
+            n @ 90..=97 => SgrAttribute::Foreground(SgrColor::Ansi((n - 90 + 8) as u8)),
+            n @ 100..=107 => SgrAttribute::Background(SgrColor::Ansi((n - 100 + 8) as u8)),
+            n @ (38 | 48 | 58) => match self.extended_color(group) {
+                Some(color) if n == 38 => SgrAttribute::Foreground(color),
+                Some(color) if n == 48 => SgrAttribute::Background(color),
+                Some(color) => SgrAttribute::UnderlineColor(color),
+                None => SgrAttribute::Unknown(&self.values[start..self.pos]),
+            },
+            _ => SgrAttribute::Unknown(group),
+        };
+        Some(attr)
+    }
+}
+
+/// Accumulates the effect of a series of [`SgrAttribute`] values.
+///
+/// A color of `None` means the terminal's default color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextStyle {
+    /// Set by [`SgrAttribute::Bold`].
+    pub bold: bool,
+    /// Set by [`SgrAttribute::Faint`].
+    pub faint: bool,
+    /// Set by [`SgrAttribute::Italic`].
+    pub italic: bool,
+    /// The style of underline, if any.
+    pub underline: Option<UnderlineStyle>,
+    /// Set by [`SgrAttribute::Blink`].
+    pub blink: bool,
+    /// Set by [`SgrAttribute::RapidBlink`].
+    pub rapid_blink: bool,
+    /// Set by [`SgrAttribute::Inverse`].
+    pub inverse: bool,
+    /// Set by [`SgrAttribute::Hidden`].
+    pub hidden: bool,
+    /// Set by [`SgrAttribute::Strikethrough`].
+    pub strikethrough: bool,
+    /// Set by [`SgrAttribute::Overline`].
+    pub overline: bool,
+    /// The text color.
+    pub foreground: Option<SgrColor>,
+    /// The background color.
+    pub background: Option<SgrColor>,
+    /// The color of underlines, or `None` to use the text color.
+    pub underline_color: Option<SgrColor>,
+}
+
+impl TextStyle {
+    /// Returns the default style, with no attributes set.
+    pub const fn new() -> Self {
+        Self {
+            bold: false,
+            faint: false,
+            italic: false,
+            underline: None,
+            blink: false,
+            rapid_blink: false,
+            inverse: false,
+            hidden: false,
+            strikethrough: false,
+            overline: false,
+            foreground: None,
+            background: None,
+            underline_color: None,
+        }
+    }
+
+    /// Updates the style to reflect the given attribute.
+    ///
+    /// [`SgrAttribute::Unknown`] has no effect.
+    pub fn apply(&mut self, attr: SgrAttribute<'_>) {
+        match attr {
+            SgrAttribute::Reset => *self = Self::new(),
+            SgrAttribute::Bold => self.bold = true,
+            SgrAttribute::Faint => self.faint = true,
+            SgrAttribute::Italic => self.italic = true,
+            SgrAttribute::Underline(style) => self.underline = Some(style),
+            SgrAttribute::Blink => self.blink = true,
+            SgrAttribute::RapidBlink => self.rapid_blink = true,
+            SgrAttribute::Inverse => self.inverse = true,
+            SgrAttribute::Hidden => self.hidden = true,
+            SgrAttribute::Strikethrough => self.strikethrough = true,
+            SgrAttribute::Overline => self.overline = true,
+            SgrAttribute::NormalIntensity => {
+                self.bold = false;
+                self.faint = false;
+            }
+            SgrAttribute::NotItalic => self.italic = false,
+            SgrAttribute::NotUnderlined => self.underline = None,
+            SgrAttribute::NotBlinking => {
+                self.blink = false;
+                self.rapid_blink = false;
+            }
+            SgrAttribute::NotInverse => self.inverse = false,
+            SgrAttribute::NotHidden => self.hidden = false,
+            SgrAttribute::NotStrikethrough => self.strikethrough = false,
+            SgrAttribute::NotOverline => self.overline = false,
+            SgrAttribute::Foreground(color) => self.foreground = Some(color),
+            SgrAttribute::DefaultForeground => self.foreground = None,
+            SgrAttribute::Background(color) => self.background = Some(color),
+            SgrAttribute::DefaultBackground => self.background = None,
+            SgrAttribute::UnderlineColor(color) => self.underline_color = Some(color),
+            SgrAttribute::DefaultUnderlineColor => self.underline_color = None,
+            SgrAttribute::Unknown(_) => {}
+        }
+    }
+
+    /// Updates the style to reflect all of the attributes described by the
+    /// given SGR parameters.
+    pub fn apply_params(&mut self, params: VtParams<'_>) {
+        for attr in SgrAttributes::new(params) {
+            self.apply(attr);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use alloc::vec::Vec;
+
+use u8char::u8char;
+
+use crate::{VtEvent, VtParams};
+
+/// Decodes sixel graphics data into an RGBA pixel buffer.
+///
+/// Sixel images arrive as a device control string with the command `q`,
+/// such as `DCS 0;1;0 q ... ST`. Use [`SixelDecoder::from_event`] with the
+/// [`VtEvent::DcsStart`] event that begins the string, pass each subsequent
+/// [`VtEvent::DcsChar`] to [`SixelDecoder::put`], and then call
+/// [`SixelDecoder::finish`] once [`VtEvent::DcsEnd`] arrives.
+///
+/// The image grows as data arrives, up to the limits set by
+/// [`SixelDecoder::with_max_size`] and [`SixelDecoder::with_max_pixels`],
+/// beyond which any further pixels are discarded. The size given in the
+/// raster attributes counts towards those limits, but memory is only
+/// allocated for it when the image is finished.
+///
+/// This type is available only when the `alloc` feature is enabled.
+pub struct SixelDecoder {
+    palette: [[u8; 4]; 256],
+    color: usize,
+    x: usize,
+    y: usize,
+    repeat: usize,
+    command: Option<u8>,
+    args: [u16; 5],
+    arg_count: usize,
+    raster: (usize, usize),
+    width: usize,
+    height: usize,
+    stride: usize,
+    rows: usize,
+    pixels: Vec<u8>,
+    max_size: (usize, usize),
+    max_pixels: usize,
+    transparent: bool,
+}
+
+impl SixelDecoder {
+    /// The default maximum width and height of a decoded image, in pixels.
+    pub const DEFAULT_MAX_SIZE: (usize, usize) = (4096, 4096);
+
+    /// The default maximum number of pixels in a decoded image, which is a
+    /// quarter of the area allowed by [`Self::DEFAULT_MAX_SIZE`].
+    pub const DEFAULT_MAX_PIXELS: usize = 4096 * 1024;
+
+    /// Begins decoding an image whose device control string has the given
+    /// parameters.
+    ///
+    /// The second parameter selects whether pixels that are not drawn are
+    /// transparent, as reported by [`SixelImage::transparent_background`].
+    /// The pixel aspect ratio and grid size parameters are ignored, so each
+    /// sixel is always one pixel wide.
+    pub fn new(params: VtParams<'_>) -> Self {
+        Self {
+            palette: DEFAULT_PALETTE,
+            color: 0,
+            x: 0,
+            y: 0,
+            repeat: 1,
+            command: None,
+            args: [0; 5],
+            arg_count: 0,
+            raster: (0, 0),
+            width: 0,
+            height: 0,
+            stride: 0,
+            rows: 0,
+            pixels: Vec::new(),
+            max_size: Self::DEFAULT_MAX_SIZE,
+            max_pixels: Self::DEFAULT_MAX_PIXELS,
+            transparent: params.get(1) == Some(1),
+        }
+    }
+
+    /// Begins decoding an image if the given event is the start of a sixel
+    /// device control string, or returns `None` otherwise.
+    pub fn from_event(event: &VtEvent<'_>) -> Option<Self> {
+        match *event {
+            VtEvent::DcsStart {
+                cmd: b'q',
+                private_marker: None,
+                params,
+                intermediates: [],
+                ..
+            } => Some(Self::new(params)),
+            _ => None,
+        }
+    }
+
+    /// Returns the decoder with a different limit on the width and height of
+    /// the image, in pixels.
+    pub fn with_max_size(mut self, width: usize, height: usize) -> Self {
+        self.max_size = (width, height);
+        self
+    }
+
+    /// Returns the decoder with a different limit on the total number of
+    /// pixels in the image, which bounds the memory it uses to four bytes
+    /// per pixel.
+    pub fn with_max_pixels(mut self, pixels: usize) -> Self {
+        self.max_pixels = pixels;
+        self
+    }
+
+    /// Consumes one character of the sixel data.
+    ///
+    /// Any character that has no meaning in sixel data is ignored.
+    pub fn put(&mut self, c: u8char) {
+        self.put_byte(c.first_byte());
+    }
+
+    /// Consumes a chunk of sixel data, such as `data` from
+    /// [`crate::VtBufferedEvent::Dcs`].
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.put_byte(*b);
+        }
+    }
+
+    fn put_byte(&mut self, b: u8) {
+        if self.command.is_some() {
+            match b {
+                b'0'..=b'9' => {
+                    if self.arg_count == 0 {
+                        self.arg_count = 1;
+                    }
+                    if let Some(arg) = self.args.get_mut(self.arg_count - 1) {
+                        *arg = arg.saturating_mul(10).saturating_add((b - b'0') as u16);
+                    }
+                    return;
+                }
+                b';' => {
+                    self.arg_count = (self.arg_count.max(1) + 1).min(self.args.len() + 1);
+                    return;
+                }
+                _ => self.end_command(),
+            }
+        }
+        match b {
+            b'!' | b'#' | b'"' => {
+                self.command = Some(b);
+                self.args = [0; 5];
+                self.arg_count = 0;
+            }
+            b'$' => self.x = 0,
+            b'-' => {
+                self.x = 0;
+                self.y += 6;
+            }
+            b'?'..=b'~' => {
+                let bits = b - b'?';
+                let count = core::mem::replace(&mut self.repeat, 1);
+                self.draw(bits, count);
+            }
+            _ => {}
+        }
+    }
+
+    fn end_command(&mut self) {
+        let args = &self.args[..self.arg_count.min(self.args.len())];
+        match self.command.take() {
+            Some(b'!') => self.repeat = args.first().copied().unwrap_or(1).max(1) as usize,
+            Some(b'#') => match *args {
+                [idx] => self.color = idx as usize % self.palette.len(),
+                [idx, space, a, b, c] => {
+                    let idx = idx as usize % self.palette.len();
+                    self.color = idx;
+                    match space {
+                        1 => self.palette[idx] = hls_to_rgba(a, b, c),
+                        2 => self.palette[idx] = percent_rgba(a, b, c),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            Some(b'"') => {
+                // Raster attributes are only meaningful before any pixel data.
+                // The size is only recorded here, and the pixels that are not
+                // drawn are filled in by `finish`.
+                if let [_, _, width, height] = *args {
+                    if self.width == 0 && self.height == 0 {
+                        let width = (width as usize).min(self.max_size.0);
+                        let height = (height as usize)
+                            .min(self.max_size.1)
+                            .min(self.max_pixels / width.max(1));
+                        self.raster = (width, height);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, bits: u8, count: usize) {
+        let (max_width, max_height) = self.max_size;
+        let end_x = self.x.saturating_add(count).min(max_width);
+        let end_y = self.y.saturating_add(6).min(max_height);
+        // The image must stay within the pixel budget including any part of
+        // the raster that has not been drawn yet.
+        let extent_x = end_x.max(self.width).max(self.raster.0);
+        let extent_y = end_y.max(self.height).max(self.raster.1);
+        if self.x < end_x && self.y < end_y && extent_x.saturating_mul(extent_y) <= self.max_pixels
+        {
+            self.reserve(end_x, end_y);
+            self.width = self.width.max(end_x);
+            self.height = self.height.max(end_y);
+            let color = self.palette[self.color];
+            for row in self.y..end_y {
+                if bits & (1 << (row - self.y)) == 0 {
+                    continue;
+                }
+                let start = (row * self.stride + self.x) * 4;
+                let end = (row * self.stride + end_x) * 4;
+                for pixel in self.pixels[start..end].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        }
+        self.x = self.x.saturating_add(count);
+    }
+
+    /// Grows the pixel buffer to hold at least the given number of columns
+    /// and rows, which must be within the pixel budget.
+    fn reserve(&mut self, width: usize, rows: usize) {
+        // Widening requires moving every row, so we grow geometrically to
+        // avoid doing that for every new column, but without letting the
+        // spare columns take the buffer over the pixel budget.
+        let limit = (self.max_pixels / rows.max(self.rows).max(1)).min(self.max_size.0);
+        let stride = if width > self.stride {
+            width.max(self.stride * 2).min(limit)
+        } else if self.stride > limit {
+            width.max(self.width)
+        } else {
+            self.stride
+        };
+        if stride != self.stride {
+            // Columns beyond the new stride, if any, have not been drawn.
+            let keep = self.stride.min(stride) * 4;
+            let mut pixels = alloc::vec![0; stride * self.rows * 4];
+            for row in 0..self.rows {
+                let old = &self.pixels[row * self.stride * 4..][..keep];
+                pixels[row * stride * 4..][..keep].copy_from_slice(old);
+            }
+            self.pixels = pixels;
+            self.stride = stride;
+        }
+        if rows > self.rows {
+            self.rows = rows;
+            self.pixels.resize(self.stride * rows * 4, 0);
+        }
+    }
+
+    /// Completes decoding and returns the resulting image.
+    ///
+    /// The image is large enough to include both the size given in the
+    /// raster attributes, if any, and all of the pixels that were drawn,
+    /// except that the last band of six rows is cropped to the raster
+    /// height if it extends beyond it.
+    pub fn finish(mut self) -> SixelImage {
+        if self.command.is_some() {
+            self.end_command();
+        }
+        let (raster_width, raster_height) = self.raster;
+        let width = self.width.max(raster_width);
+        let mut height = self.height.max(raster_height);
+        if raster_height != 0 && height > raster_height && height - raster_height < 6 {
+            height = raster_height;
+        }
+        // The buffer can be used as it is if it has no spare columns.
+        // Otherwise the rows are copied, filling in any columns and rows of
+        // the raster that were not drawn.
+        let pixels = if self.stride == width {
+            self.pixels.resize(width * height * 4, 0);
+            self.pixels
+        } else {
+            let mut pixels = Vec::with_capacity(width * height * 4);
+            let keep = self.stride.min(width) * 4;
+            for row in 0..height {
+                if row < self.rows {
+                    pixels.extend_from_slice(&self.pixels[row * self.stride * 4..][..keep]);
+                }
+                pixels.resize((row + 1) * width * 4, 0);
+            }
+            pixels
+        };
+        SixelImage {
+            width,
+            height,
+            pixels,
+            transparent: self.transparent,
+        }
+    }
+}
+
+/// An image decoded by [`SixelDecoder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SixelImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    transparent: bool,
+}
+
+impl SixelImage {
+    /// Returns the width of the image, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the image, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the pixels as rows of 8-bit red, green, blue and alpha
+    /// values, starting at the top left.
+    ///
+    /// Pixels that were not drawn are all zeros, including alpha.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Returns the pixel buffer described by [`Self::pixels`].
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    /// Returns true if the program asked for pixels that were not drawn to
+    /// be left transparent, or false if they should be filled with the
+    /// terminal's background color.
+    pub fn transparent_background(&self) -> bool {
+        self.transparent
+    }
+}
+
+const fn percent(v: u16) -> u8 {
+    let v = if v > 100 { 100 } else { v as u32 };
+    ((v * 255 + 50) / 100) as u8
+}
+
+const fn percent_rgba(r: u16, g: u16, b: u16) -> [u8; 4] {
+    [percent(r), percent(g), percent(b), 255]
+}
+
+/// Converts a color given in the sixel variant of HLS, where a hue of zero
+/// is blue rather than red, to RGBA.
+fn hls_to_rgba(h: u16, l: u16, s: u16) -> [u8; 4] {
+    let l = l.min(100) as f32 / 100.0;
+    let s = s.min(100) as f32 / 100.0;
+    if s == 0.0 {
+        let v = (l * 255.0 + 0.5) as u8;
+        return [v, v, v, 255];
+    }
+    let h = ((h as u32 + 240) % 360) as f32 / 360.0;
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f32| {
+        let t = if t < 0.0 {
+            t + 1.0
+        } else if t >= 1.0 {
+            t - 1.0
+        } else {
+            t
+        };
+        let v = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        (v * 255.0 + 0.5) as u8
+    };
+    [
+        channel(h + 1.0 / 3.0),
+        channel(h),
+        channel(h - 1.0 / 3.0),
+        255,
+    ]
+}
+
+/// The initial color palette of the VT340, which most sixel encoders assume
+/// for the first 16 entries. The remaining entries start as black.
+const DEFAULT_PALETTE: [[u8; 4]; 256] = {
+    const PERCENTAGES: [(u16, u16, u16); 16] = [
+        (0, 0, 0),
+        (20, 20, 80),
+        (80, 13, 13),
+        (20, 80, 20),
+        (80, 20, 80),
+        (20, 80, 80),
+        (80, 80, 20),
+        (53, 53, 53),
+        (26, 26, 26),
+        (33, 33, 60),
+        (60, 26, 26),
+        (33, 60, 33),
+        (60, 33, 60),
+        (33, 60, 60),
+        (60, 60, 33),
+        (80, 80, 80),
+    ];
+    let mut palette = [[0, 0, 0, 255]; 256];
+    let mut idx = 0;
+    while idx < PERCENTAGES.len() {
+        let (r, g, b) = PERCENTAGES[idx];
+        palette[idx] = percent_rgba(r, g, b);
+        idx += 1;
+    }
+    palette
+};
@@ -53,8 +53,10 @@ fn format_csi() {
         VtEvent::PrintEnd,
         VtEvent::DispatchCsi {
             cmd: 'm' as u8,
-            params: &[1],
+            private_marker: None,
+            params: VtParams::from_values(&[1]),
             intermediates: &[],
+            truncated: false,
         },
         print_event!('b'),
         print_event!('o'),
@@ -63,8 +65,10 @@ fn format_csi() {
         VtEvent::PrintEnd,
         VtEvent::DispatchCsi {
             cmd: 'p' as u8,
-            params: &[2, 3],
+            private_marker: None,
+            params: VtParams::from_values(&[2, 3]),
             intermediates: &[],
+            truncated: false,
         },
         print_event!('m'),
         print_event!('o'),
@@ -106,15 +110,19 @@ fn through_u8char_stream() {
         VtEvent::PrintEnd,
         VtEvent::DispatchCsi {
             cmd: 'm' as u8,
-            params: &[1],
+            private_marker: None,
+            params: VtParams::from_values(&[1]),
             intermediates: &[],
+            truncated: false,
         },
         print_event!('❞'),
         VtEvent::PrintEnd,
         VtEvent::DispatchCsi {
             cmd: 'm' as u8,
-            params: &[0],
+            private_marker: None,
+            params: VtParams::from_values(&[0]),
             intermediates: &[],
+            truncated: false,
         },
         print_event!('\u{FFFD}'),
         print_event!('c'),
@@ -124,7 +132,1679 @@ fn through_u8char_stream() {
     assert_eq!(got, want);
 }
 
-fn collect_events(machine: &mut VtMachine, input: &str) -> Vec<String> {
+#[test]
+fn write_bytes() {
+    use std::format;
+
+    // This should produce the same events as the manual U8CharStream
+    // pipeline in through_u8char_stream, regardless of how the input
+    // is split into chunks.
+    let input = b"a\x1b[1m\xe2\x9d\x9e\x1b[0m\x9dc\xe2";
+    let want = want_events(&[
+        print_event!('a'),
+        VtEvent::PrintEnd,
+        VtEvent::DispatchCsi {
+            cmd: 'm' as u8,
+            private_marker: None,
+            params: VtParams::from_values(&[1]),
+            intermediates: &[],
+            truncated: false,
+        },
+        print_event!('❞'),
+        VtEvent::PrintEnd,
+        VtEvent::DispatchCsi {
+            cmd: 'm' as u8,
+            private_marker: None,
+            params: VtParams::from_values(&[0]),
+            intermediates: &[],
+            truncated: false,
+        },
+        print_event!('\u{FFFD}'),
+        print_event!('c'),
+        print_event!('\u{FFFD}'),
+        VtEvent::PrintEnd,
+    ]);
+
+    for chunk_size in 1..=input.len() {
+        let mut m = VtMachine::new();
+        let mut got: Vec<String> = Vec::new();
+        for chunk in input.chunks(chunk_size) {
+            m.write_bytes(chunk, |event| got.push(format!("{event:?}")));
+        }
+        m.write_bytes_end(|event| got.push(format!("{event:?}")));
+        assert_eq!(got, want, "chunk size {chunk_size}");
+    }
+}
+
+#[test]
+fn advance_handler() {
+    use std::format;
+
+    struct Recorder(Vec<String>);
+
+    impl VtHandler for Recorder {
+        fn print(&mut self, c: u8char) {
+            self.0.push(format!("{:?}", VtEvent::Print(c)));
+        }
+        fn print_end(&mut self) {
+            self.0.push(format!("{:?}", VtEvent::PrintEnd));
+        }
+        fn execute(&mut self, ctrl: u8) {
+            self.0.push(format!("{:?}", VtEvent::ExecuteCtrl(ctrl)));
+        }
+        fn csi_dispatch(
+            &mut self,
+            cmd: u8,
+            private_marker: Option<u8>,
+            params: VtParams<'_>,
+            intermediates: &[u8],
+            truncated: bool,
+        ) {
+            let event = VtEvent::DispatchCsi {
+                cmd,
+                private_marker,
+                params,
+                intermediates,
+                truncated,
+            };
+            self.0.push(format!("{event:?}"));
+        }
+        fn osc_start(&mut self, introducer: u8) {
+            self.0.push(format!("{:?}", VtEvent::OscStart(introducer)));
+        }
+        fn osc_put(&mut self, c: u8char) {
+            self.0.push(format!("{:?}", VtEvent::OscChar(c)));
+        }
+        fn osc_end(&mut self, terminator: u8) {
+            self.0.push(format!("{:?}", VtEvent::OscEnd(terminator)));
+        }
+    }
+
+    let mut m = VtMachine::new();
+    let mut recorder = Recorder(Vec::new());
+    m.advance(&mut recorder, b"hi\r\x1b[2;4H\x1b]2x\x18");
+    m.advance_end(&mut recorder);
+
+    let want = want_events(&[
+        print_event!('h'),
+        print_event!('i'),
+        VtEvent::PrintEnd,
+        VtEvent::ExecuteCtrl(b'\r'),
+        VtEvent::DispatchCsi {
+            cmd: b'H',
+            private_marker: None,
+            params: VtParams::from_values(&[2, 4]),
+            intermediates: &[],
+            truncated: false,
+        },
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('2')),
+        VtEvent::OscChar(u8char::from_char('x')),
+        VtEvent::OscEnd(b'\x18'),
+        VtEvent::ExecuteCtrl(b'\x18'),
+    ]);
+    assert_eq!(recorder.0, want);
+}
+
+#[test]
+fn osc_bel_terminator() {
+    let mut m = VtMachine::new();
+    let got = collect_events(&mut m, "\x1b]0;hi\x07ok");
+    let want = want_events(&[
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('0')),
+        VtEvent::OscChar(u8char::from_char(';')),
+        VtEvent::OscChar(u8char::from_char('h')),
+        VtEvent::OscChar(u8char::from_char('i')),
+        VtEvent::OscEnd(b'\x07'),
+        print_event!('o'),
+        print_event!('k'),
+        VtEvent::PrintEnd,
+    ]);
+    assert_eq!(got, want);
+
+    // With the BEL terminator disabled, BEL is ignored and the following
+    // text is still part of the command.
+    let mut m = VtMachine::new().with_osc_bel_terminator(false);
+    let got = collect_events(&mut m, "\x1b]0;hi\x07ok\x18");
+    let want = want_events(&[
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('0')),
+        VtEvent::OscChar(u8char::from_char(';')),
+        VtEvent::OscChar(u8char::from_char('h')),
+        VtEvent::OscChar(u8char::from_char('i')),
+        VtEvent::OscChar(u8char::from_char('o')),
+        VtEvent::OscChar(u8char::from_char('k')),
+        VtEvent::OscEnd(b'\x18'),
+        VtEvent::ExecuteCtrl(b'\x18'),
+    ]);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn string_terminator() {
+    let mut m = VtMachine::new();
+    let got = collect_events(&mut m, "\x1b]0;a\x1b\\\x1bPqb\x1b\\\x1b_c\x1b\\d");
+    let want = want_events(&[
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('0')),
+        VtEvent::OscChar(u8char::from_char(';')),
+        VtEvent::OscChar(u8char::from_char('a')),
+        VtEvent::OscEnd(b'\x9c'),
+        VtEvent::DcsStart {
+            cmd: b'q',
+            private_marker: None,
+            params: VtParams::from_values(&[]),
+            intermediates: &[],
+            truncated: false,
+        },
+        VtEvent::DcsChar(u8char::from_char('b')),
+        VtEvent::DcsEnd(b'\x9c'),
+        VtEvent::SosPmApcStart(b'_'),
+        VtEvent::SosPmApcChar(u8char::from_char('c')),
+        VtEvent::SosPmApcEnd(b'\x9c'),
+        print_event!('d'),
+        VtEvent::PrintEnd,
+    ]);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn string_interrupted_by_escape() {
+    // An ESC that isn't followed by a backslash ends the string and then
+    // begins a new escape sequence.
+    let mut m = VtMachine::new();
+    let got = collect_events(&mut m, "\x1b]0;a\x1b[1m\x1bPqb\x1b7\x1b]x\x1b\x1b\\");
+    let want = want_events(&[
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('0')),
+        VtEvent::OscChar(u8char::from_char(';')),
+        VtEvent::OscChar(u8char::from_char('a')),
+        VtEvent::OscEnd(b'\x1b'),
+        VtEvent::DispatchCsi {
+            cmd: b'm',
+            private_marker: None,
+            params: VtParams::from_values(&[1]),
+            intermediates: &[],
+            truncated: false,
+        },
+        VtEvent::DcsStart {
+            cmd: b'q',
+            private_marker: None,
+            params: VtParams::from_values(&[]),
+            intermediates: &[],
+            truncated: false,
+        },
+        VtEvent::DcsChar(u8char::from_char('b')),
+        VtEvent::DcsEnd(b'\x1b'),
+        VtEvent::DispatchEsc {
+            cmd: b'7',
+            intermediates: &[],
+        },
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('x')),
+        VtEvent::OscEnd(b'\x1b'),
+        VtEvent::DispatchEsc {
+            cmd: b'\\',
+            intermediates: &[],
+        },
+    ]);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn string_non_ascii() {
+    let mut m = VtMachine::new();
+    let got = collect_events(
+        &mut m,
+        "\x1b]0;café\x07\x1bP+q☃\x1b\\\x1b_日\u{9c}\x1b^ü\x1b\\x",
+    );
+    let want = want_events(&[
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('0')),
+        VtEvent::OscChar(u8char::from_char(';')),
+        VtEvent::OscChar(u8char::from_char('c')),
+        VtEvent::OscChar(u8char::from_char('a')),
+        VtEvent::OscChar(u8char::from_char('f')),
+        VtEvent::OscChar(u8char::from_char('é')),
+        VtEvent::OscEnd(0x07),
+        VtEvent::DcsStart {
+            cmd: b'q',
+            private_marker: None,
+            params: VtParams::from_values(&[]),
+            intermediates: b"+",
+            truncated: false,
+        },
+        VtEvent::DcsChar(u8char::from_char('☃')),
+        VtEvent::DcsEnd(0x9c),
+        VtEvent::SosPmApcStart(b'_'),
+        VtEvent::SosPmApcChar(u8char::from_char('日')),
+        VtEvent::SosPmApcEnd(0x9c),
+        VtEvent::SosPmApcStart(b'^'),
+        VtEvent::SosPmApcChar(u8char::from_char('ü')),
+        VtEvent::SosPmApcEnd(0x9c),
+        VtEvent::Print(u8char::from_char('x')),
+        VtEvent::PrintEnd,
+    ]);
+    assert_eq!(got, want);
+
+    // Non-ASCII characters are ignored along with everything else in a
+    // string that the machine doesn't report.
+    let mut m = VtMachine::new().with_sos_pm_apc_strings(false);
+    let got = collect_events(&mut m, "\x1b_日本\x1b\\x");
+    let want = want_events(&[VtEvent::Print(u8char::from_char('x')), VtEvent::PrintEnd]);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn csi_subparams() {
+    let mut m = VtMachine::new();
+    let mut groups: Vec<Vec<u16>> = Vec::new();
+    let mut got: Vec<String> = Vec::new();
+    for c in "\x1b[38:2::255:0:0;4:3;1m".chars() {
+        for event in m.write_char(c) {
+            if let VtEvent::DispatchCsi { params, .. } = event {
+                groups.extend(params.groups().map(|group| group.to_vec()));
+            }
+            got.push(std::format!("{event:?}"));
+        }
+    }
+    assert_eq!(
+        groups,
+        std::vec![
+            std::vec![38, 2, 0, 255, 0, 0],
+            std::vec![4, 3],
+            std::vec![1]
+        ],
+    );
+    assert_eq!(
+        got,
+        std::vec!["DispatchCsi { cmd: 109, private_marker: None, params: [38:2:_:255:0:0, 4:3, 1], intermediates: [], truncated: false }"],
+    );
+}
+
+#[test]
+fn csi_default_params() {
+    let mut m = VtMachine::new();
+    let got = collect_events(
+        &mut m,
+        "\x1b[;5H\x1b[0;5H\x1b[m\x1b[;m\x1b[2;H\x1b[38:2::1:2:3m",
+    );
+    let want = std::vec![
+        "DispatchCsi { cmd: 72, private_marker: None, params: [_, 5], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 72, private_marker: None, params: [0, 5], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 109, private_marker: None, params: [], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 109, private_marker: None, params: [_, _], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 72, private_marker: None, params: [2, _], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 109, private_marker: None, params: [38:2:_:1:2:3], intermediates: [], truncated: false }",
+    ];
+    assert_eq!(got, want);
+
+    let mut m = VtMachine::new();
+    for c in "\x1b[;5;0".chars() {
+        assert_eq!(m.write_char(c).count(), 0);
+    }
+    let Some(VtEvent::DispatchCsi { params, .. }) = m.write_char('H').next() else {
+        panic!("CUP sequence did not produce DispatchCsi");
+    };
+    assert_eq!(params.len(), 3);
+    assert_eq!(params.get(0), None);
+    assert_eq!(params.get(1), Some(5));
+    assert_eq!(params.get(2), Some(0));
+    assert_eq!(params.get(3), None);
+    assert_eq!(params.param_or(0, 1), 1);
+    assert_eq!(params.param_or(2, 1), 0);
+    assert_eq!(params.param_or(3, 1), 1);
+    assert_eq!(
+        params.iter().collect::<Vec<_>>(),
+        std::vec![None, Some(5), Some(0)]
+    );
+}
+
+#[test]
+fn csi_param_overflow() {
+    let mut m = VtMachine::new();
+    let got = collect_events(
+        &mut m,
+        "\x1b[99999999H\x1b[65535;65536H\x1bP1;999999999999999999999q\x1b\\",
+    );
+    let want = std::vec![
+        "DispatchCsi { cmd: 72, private_marker: None, params: [>65535], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 72, private_marker: None, params: [65535, >65535], intermediates: [], truncated: false }",
+        "DcsStart { cmd: 113, private_marker: None, params: [1, >65535], intermediates: [], truncated: false }",
+        "DcsEnd(156)",
+    ];
+    assert_eq!(got, want);
+
+    let mut m = VtMachine::new();
+    for c in "\x1b[1;0000000000000000000000000065535;65536".chars() {
+        assert_eq!(m.write_char(c).count(), 0);
+    }
+    let Some(VtEvent::DispatchCsi { params, .. }) = m.write_char('m').next() else {
+        panic!("SGR sequence did not produce DispatchCsi");
+    };
+    assert!(params.has_overflow());
+    assert!(!params.is_overflowed(0));
+    assert!(!params.is_overflowed(1));
+    assert!(params.is_overflowed(2));
+    assert_eq!(params.values(), &[1, 65535, 65535]);
+}
+
+#[test]
+fn csi_param_fuzz() {
+    // A tiny deterministic pseudorandom generator, so that this test covers
+    // a wide variety of digit sequences without any extra dependencies.
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    for _ in 0..2000 {
+        let count = (next() % 5) as usize + 1;
+        let mut input = String::from("\x1b[");
+        let mut want: Vec<(u16, bool)> = Vec::new();
+        for i in 0..count {
+            if i != 0 {
+                input.push(';');
+            }
+            let digits = (next() % 30) as usize + 1;
+            let mut exact: u128 = 0;
+            for _ in 0..digits {
+                let digit = (next() % 10) as u8;
+                input.push((b'0' + digit) as char);
+                exact = exact.saturating_mul(10).saturating_add(digit as u128);
+            }
+            if exact > u16::MAX as u128 {
+                want.push((u16::MAX, true));
+            } else {
+                want.push((exact as u16, false));
+            }
+        }
+
+        let mut m = VtMachine::new();
+        for c in input.chars() {
+            assert_eq!(m.write_char(c).count(), 0, "input {input:?}");
+        }
+        let Some(VtEvent::DispatchCsi { params, .. }) = m.write_char('H').next() else {
+            panic!("input {input:?} did not produce DispatchCsi");
+        };
+        let got: Vec<(u16, bool)> = (0..params.values().len())
+            .map(|idx| (params.values()[idx], params.is_overflowed(idx)))
+            .collect();
+        assert_eq!(got, want, "input {input:?}");
+    }
+
+    // Arbitrary bytes must never cause a panic, either.
+    for _ in 0..2000 {
+        let mut m = VtMachine::new();
+        let len = (next() % 64) as usize;
+        let interesting = b"\x1b[P];:0123456789";
+        let bytes: Vec<u8> = (0..len)
+            .map(|_| match next() % 4 {
+                0 => interesting[(next() as usize) % interesting.len()],
+                _ => (next() % 256) as u8,
+            })
+            .collect();
+        m.write_bytes(&bytes, |_| {});
+        m.write_bytes_end(|_| {});
+    }
+}
+
+#[test]
+fn capacity_truncation() {
+    let mut m = VtMachine::<2, 1>::new_with_capacity();
+    let got = collect_events(
+        &mut m,
+        "\x1b[1;2H\x1b[1;2;3H\x1b[1:2:3m\x1b(B\x1b($B\x1bP1;2;3q\x1b\\",
+    );
+    let want = std::vec![
+        "DispatchCsi { cmd: 72, private_marker: None, params: [1, 2], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 72, private_marker: None, params: [1, 2], intermediates: [], truncated: true }",
+        "DispatchCsi { cmd: 109, private_marker: None, params: [1:2], intermediates: [], truncated: true }",
+        "DispatchEsc { cmd: 66, intermediates: [40] }",
+        "Error { c: u8char('B'), kind: TooManyIntermediates, state: EscapeIntermediate }",
+        "DcsStart { cmd: 113, private_marker: None, params: [1, 2], intermediates: [], truncated: true }",
+        "DcsEnd(156)",
+    ];
+    assert_eq!(got, want);
+
+    let mut m = VtMachine::<32, 4>::new_with_capacity();
+    let got = collect_events(&mut m, "\x1b[1;2;3;4;5;6;7;8;9;10;11;12;13;14;15;16;17;18m");
+    let want = std::vec![
+        "DispatchCsi { cmd: 109, private_marker: None, params: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18], intermediates: [], truncated: false }",
+    ];
+    assert_eq!(got, want);
+}
+
+#[test]
+fn intermediates_overrun() {
+    let mut m = VtMachine::new();
+    let got = collect_events(
+        &mut m,
+        "\x1b !\"X\x1b[1 !\"p\x1bP1 !\"qdata\x1b\\\x1b !Y\x1b[1 !p",
+    );
+    let want = std::vec![
+        "Error { c: u8char('X'), kind: TooManyIntermediates, state: EscapeIntermediate }",
+        "Error { c: u8char('p'), kind: TooManyIntermediates, state: CsiIntermediate }",
+        "Error { c: u8char('q'), kind: TooManyIntermediates, state: DcsIntermediate }",
+        "DispatchEsc { cmd: 89, intermediates: [32, 33] }",
+        "DispatchCsi { cmd: 112, private_marker: None, params: [1], intermediates: [32, 33], truncated: false }",
+    ];
+    assert_eq!(got, want);
+}
+
+#[test]
+fn error_recovery() {
+    let input = "\x1b[1é\x1bPé\x1bé!";
+    let mut m = VtMachine::new();
+    let got = collect_events(&mut m, input);
+    let want = want_events(&[
+        VtEvent::Error {
+            c: u8char::from_char('é'),
+            kind: VtErrorKind::UnexpectedChar,
+            state: VtState::CsiParam,
+        },
+        VtEvent::Error {
+            c: u8char::from_char('é'),
+            kind: VtErrorKind::UnexpectedChar,
+            state: VtState::DcsEntry,
+        },
+        VtEvent::Error {
+            c: u8char::from_char('é'),
+            kind: VtErrorKind::UnexpectedChar,
+            state: VtState::Escape,
+        },
+        VtEvent::Print(u8char::from_char('!')),
+        VtEvent::PrintEnd,
+    ]);
+    assert_eq!(got, want);
+
+    let mut m = VtMachine::new().with_error_reprocessing(true);
+    let got = collect_events(&mut m, input);
+    let want = want_events(&[
+        VtEvent::Error {
+            c: u8char::from_char('é'),
+            kind: VtErrorKind::UnexpectedChar,
+            state: VtState::CsiParam,
+        },
+        VtEvent::Print(u8char::from_char('é')),
+        VtEvent::PrintEnd,
+        VtEvent::Error {
+            c: u8char::from_char('é'),
+            kind: VtErrorKind::UnexpectedChar,
+            state: VtState::DcsEntry,
+        },
+        VtEvent::Print(u8char::from_char('é')),
+        VtEvent::PrintEnd,
+        VtEvent::Error {
+            c: u8char::from_char('é'),
+            kind: VtErrorKind::UnexpectedChar,
+            state: VtState::Escape,
+        },
+        VtEvent::Print(u8char::from_char('é')),
+        VtEvent::Print(u8char::from_char('!')),
+        VtEvent::PrintEnd,
+    ]);
+    assert_eq!(got, want);
+
+    // Too many intermediates is not affected by reprocessing, because the
+    // final character is an expected part of the sequence.
+    let got = collect_events(&mut m, "\x1b[1 !\"p");
+    let want =
+        std::vec!["Error { c: u8char('p'), kind: TooManyIntermediates, state: CsiIntermediate }",];
+    assert_eq!(got, want);
+
+    // An ESC that ends a string and then starts an erroneous sequence
+    // produces both the end of the string and the error ahead of the
+    // reprocessed character.
+    let got = collect_events(&mut m, "\x1b]0\x1bé");
+    let want = want_events(&[
+        VtEvent::OscStart(b']'),
+        VtEvent::OscChar(u8char::from_char('0')),
+        VtEvent::OscEnd(b'\x1b'),
+        VtEvent::Error {
+            c: u8char::from_char('é'),
+            kind: VtErrorKind::UnexpectedChar,
+            state: VtState::Escape,
+        },
+        VtEvent::Print(u8char::from_char('é')),
+        VtEvent::PrintEnd,
+    ]);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn state_and_reset() {
+    let mut m = VtMachine::new();
+    assert_eq!(m.state(), VtState::Ground);
+    assert!(m.is_ground());
+
+    let steps: &[(char, VtState)] = &[
+        ('\x1b', VtState::Escape),
+        ('[', VtState::CsiEntry),
+        ('?', VtState::CsiParam),
+        ('1', VtState::CsiParam),
+        (' ', VtState::CsiIntermediate),
+        ('h', VtState::Ground),
+        ('\x1b', VtState::Escape),
+        (']', VtState::OscString),
+        ('\x1b', VtState::OscString),
+        ('\\', VtState::Ground),
+        ('\x1b', VtState::Escape),
+        ('P', VtState::DcsEntry),
+        ('q', VtState::DcsPassthrough),
+    ];
+    for (c, want) in steps {
+        m.write_char(*c).for_each(drop);
+        assert_eq!(m.state(), *want, "after {c:?}");
+    }
+    assert!(!m.is_ground());
+
+    // After a reset, the machine produces no events for the abandoned
+    // string and treats the next character as the first in a new stream.
+    m.reset();
+    assert!(m.is_ground());
+    let got = collect_events(&mut m, "a");
+    let want = want_events(&[VtEvent::Print(u8char::from_char('a')), VtEvent::PrintEnd]);
+    assert_eq!(got, want);
+
+    let mut m = VtMachine::new();
+    m.write_char('a').for_each(drop);
+    m.write_char('\x1b').for_each(drop);
+    m.write_char('[').for_each(drop);
+    m.write_char('1').for_each(drop);
+    m.reset();
+    let got = collect_events(&mut m, "m");
+    let want = want_events(&[VtEvent::Print(u8char::from_char('m')), VtEvent::PrintEnd]);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn snapshot_restore() {
+    use std::format;
+
+    // The input stops part way through both a control sequence and a UTF-8
+    // sequence.
+    let input = b"ab\x1b[?1;2:3 \xe2\x98";
+    let rest = b"\x83p\x1b[1m";
+
+    let mut m = VtMachine::new();
+    m.write_bytes(input, |_| {});
+    let mut buf = [0_u8; VtMachine::<16, 2>::SNAPSHOT_MAX_LEN];
+    let len = m.snapshot(&mut buf).unwrap();
+    let snapshot = &buf[..len];
+
+    // A clone and a restored machine both continue exactly where the
+    // original left off, even with different capacities.
+    let mut cloned = m.clone();
+    assert_eq!(cloned, m);
+    let mut restored = VtMachine::<4, 1>::new_with_capacity();
+    restored.restore(snapshot).unwrap();
+    assert_eq!(restored.state(), VtState::CsiIntermediate);
+
+    let mut want: Vec<String> = Vec::new();
+    m.write_bytes(rest, |event| want.push(format!("{event:?}")));
+    assert_eq!(
+        want[..2],
+        [
+            "Error { c: u8char('☃'), kind: UnexpectedChar, state: CsiIntermediate }",
+            "Print(u8char('p'))",
+        ]
+    );
+    let mut got: Vec<String> = Vec::new();
+    cloned.write_bytes(rest, |event| got.push(format!("{event:?}")));
+    assert_eq!(got, want);
+    let mut got: Vec<String> = Vec::new();
+    restored.write_bytes(rest, |event| got.push(format!("{event:?}")));
+    assert_eq!(got, want);
+
+    let mut small = VtMachine::<1, 2>::new_with_capacity();
+    assert_eq!(
+        small.restore(snapshot),
+        Err(VtSnapshotError::CapacityExceeded)
+    );
+    assert!(small.is_ground());
+    assert_eq!(
+        VtMachine::new().restore(&snapshot[..len - 1]),
+        Err(VtSnapshotError::Malformed)
+    );
+    let mut bad_version = snapshot.to_vec();
+    bad_version[0] = 99;
+    assert_eq!(
+        VtMachine::new().restore(&bad_version),
+        Err(VtSnapshotError::UnsupportedVersion(99))
+    );
+    assert_eq!(
+        m.snapshot(&mut [0; 4]),
+        Err(VtSnapshotError::BufferTooSmall)
+    );
+}
+
+#[test]
+fn private_marker() {
+    let mut m = VtMachine::<16, 1>::new_with_capacity();
+    let got = collect_events(
+        &mut m,
+        "\x1b[?1049h\x1b[>4;1m\x1b[?$p\x1b[1?h\x1bP>|x\x1b\\",
+    );
+    let want = std::vec![
+        "DispatchCsi { cmd: 104, private_marker: Some(63), params: [1049], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 109, private_marker: Some(62), params: [4, 1], intermediates: [], truncated: false }",
+        "DispatchCsi { cmd: 112, private_marker: Some(63), params: [], intermediates: [36], truncated: false }",
+        "DcsStart { cmd: 124, private_marker: Some(62), params: [], intermediates: [], truncated: false }",
+        "DcsChar(u8char('x'))",
+        "DcsEnd(156)",
+    ];
+    assert_eq!(got, want);
+}
+
+#[test]
+fn c1_controls() {
+    let mut m = VtMachine::new();
+    let got = collect_events(&mut m, "a\u{85}\u{9b}1m\u{9d}0;x\u{9c}\u{90}qy\u{9c}b");
+    let want = std::vec![
+        "Print(u8char('a'))",
+        "PrintEnd",
+        "ExecuteCtrl(133)",
+        "DispatchCsi { cmd: 109, private_marker: None, params: [1], intermediates: [], truncated: false }",
+        "OscStart(157)",
+        "OscChar(u8char('0'))",
+        "OscChar(u8char(';'))",
+        "OscChar(u8char('x'))",
+        "OscEnd(156)",
+        "DcsStart { cmd: 113, private_marker: None, params: [], intermediates: [], truncated: false }",
+        "DcsChar(u8char('y'))",
+        "DcsEnd(156)",
+        "Print(u8char('b'))",
+        "PrintEnd",
+    ];
+    assert_eq!(got, want);
+
+    let mut m = VtMachine::new().with_c1_controls(false);
+    let got = collect_events(&mut m, "a\u{85}\u{9b}1m");
+    let want = want_events(&[
+        print_event!('a'),
+        print_event!('\u{85}'),
+        print_event!('\u{9b}'),
+        print_event!('1'),
+        print_event!('m'),
+        VtEvent::PrintEnd,
+    ]);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn end_in_each_state() {
+    let tests: &[(&str, &[&str])] = &[
+        ("\x1b", &[]),
+        ("\x1b(", &[]),
+        ("\x1b[", &[]),
+        ("\x1b[?1;2", &[]),
+        ("\x1b[1$", &[]),
+        ("\x1b[1:2<", &[]),
+        ("\x1bP", &[]),
+        ("\x1bP1;2", &[]),
+        ("\x1bP1$", &[]),
+        ("\x1bP1<", &[]),
+        ("\x1bP1<q", &[]),
+        (
+            "\x1b_x",
+            &["SosPmApcStart(95)", "SosPmApcChar(u8char('x'))", "SosPmApcEnd(4)"],
+        ),
+        (
+            "\x1b_x\x1b",
+            &["SosPmApcStart(95)", "SosPmApcChar(u8char('x'))", "SosPmApcEnd(4)"],
+        ),
+        ("\x1bP1<q\x1b", &[]),
+        (
+            "\x1bPqab",
+            &[
+                "DcsStart { cmd: 113, private_marker: None, params: [], intermediates: [], truncated: false }",
+                "DcsChar(u8char('a'))",
+                "DcsChar(u8char('b'))",
+                "DcsEnd(4)",
+            ],
+        ),
+        (
+            "\x1bPqa\x1b",
+            &[
+                "DcsStart { cmd: 113, private_marker: None, params: [], intermediates: [], truncated: false }",
+                "DcsChar(u8char('a'))",
+                "DcsEnd(4)",
+            ],
+        ),
+        (
+            "\x1b]0;a",
+            &[
+                "OscStart(93)",
+                "OscChar(u8char('0'))",
+                "OscChar(u8char(';'))",
+                "OscChar(u8char('a'))",
+                "OscEnd(4)",
+            ],
+        ),
+        (
+            "\x1b]a\x1b",
+            &["OscStart(93)", "OscChar(u8char('a'))", "OscEnd(4)"],
+        ),
+    ];
+
+    for (input, want) in tests {
+        let mut m = VtMachine::new();
+        let mut got = collect_events(&mut m, input);
+        // The machine must be back in the literal state afterwards.
+        got.extend(collect_events(&mut m, "z"));
+        let mut want: Vec<String> = want.iter().map(|s| String::from(*s)).collect();
+        want.push(String::from("Print(u8char('z'))"));
+        want.push(String::from("PrintEnd"));
+        assert_eq!(got, want, "input {input:?}");
+    }
+}
+
+#[test]
+fn sos_pm_apc_strings() {
+    let mut m = VtMachine::new();
+    let got = collect_events(&mut m, "\x1b_Ga=T\x1b\\\x1b^p\u{9c}\x1bXs\x1b[1m");
+    let want = std::vec![
+        "SosPmApcStart(95)",
+        "SosPmApcChar(u8char('G'))",
+        "SosPmApcChar(u8char('a'))",
+        "SosPmApcChar(u8char('='))",
+        "SosPmApcChar(u8char('T'))",
+        "SosPmApcEnd(156)",
+        "SosPmApcStart(94)",
+        "SosPmApcChar(u8char('p'))",
+        "SosPmApcEnd(156)",
+        "SosPmApcStart(88)",
+        "SosPmApcChar(u8char('s'))",
+        "SosPmApcEnd(27)",
+        "DispatchCsi { cmd: 109, private_marker: None, params: [1], intermediates: [], truncated: false }",
+    ];
+    assert_eq!(got, want);
+
+    let mut m = VtMachine::new().with_sos_pm_apc_strings(false);
+    let got = collect_events(&mut m, "\x1b_Ga=T\x1b\\\x1b^p\u{9c}\x1bXs\x1b[1m");
+    let want = std::vec![
+        "DispatchCsi { cmd: 109, private_marker: None, params: [1], intermediates: [], truncated: false }",
+    ];
+    assert_eq!(got, want);
+}
+
+#[test]
+fn csi_commands() {
+    use ::u8char::AsU8Chars;
+    use std::format;
+
+    let mut m = VtMachine::new();
+    let mut got: Vec<String> = Vec::new();
+    let input =
+        "\x1b[A\x1b[0;5H\x1b[;7f\x1b[2J\x1b[3K\x1b[?25;1049h\x1b[5;r\x1b[4 q\x1b[!p\x1b[>c\x1b[=5u";
+    for c in input.u8chars() {
+        for event in m.write_u8char(c) {
+            if let Some(cmd) = event.csi_command() {
+                got.push(format!("{cmd:?}"));
+            }
+        }
+    }
+    let want = std::vec![
+        "CursorUp(1)",
+        "CursorPosition { row: 1, col: 5 }",
+        "CursorPosition { row: 1, col: 7 }",
+        "EraseInDisplay(All)",
+        "Unknown { cmd: 75, private_marker: None, params: [3], intermediates: [] }",
+        "DecSet([25, 1049])",
+        "SetScrollingRegion { top: 5, bottom: None }",
+        "SetCursorStyle(4)",
+        "SoftReset",
+        "SecondaryDeviceAttributes",
+        "Unknown { cmd: 117, private_marker: Some(61), params: [5], intermediates: [] }",
+    ];
+    assert_eq!(got, want);
+
+    // A sequence with more parameters than the machine can hold is not
+    // decoded, since the parameters that were discarded might matter.
+    let mut m = VtMachine::<2, 2>::new_with_capacity();
+    let mut got: Vec<String> = Vec::new();
+    for c in "\x1b[?25;1049;2004h\x1b[?25;1049h".u8chars() {
+        for event in m.write_u8char(c) {
+            if let Some(cmd) = event.csi_command() {
+                got.push(format!("{cmd:?}"));
+            }
+        }
+    }
+    let want = std::vec![
+        "Unknown { cmd: 104, private_marker: Some(63), params: [25, 1049], intermediates: [] }",
+        "DecSet([25, 1049])",
+    ];
+    assert_eq!(got, want);
+}
+
+#[test]
+fn sgr_attributes() {
+    use std::format;
+
+    let mut m = VtMachine::<32>::new_with_capacity();
+    let input = "\x1b[1;4:3;38;2;255;128;0;48:2::1:2:3;58:5:9;38;5;300;93;4:9m\x1b[m";
+    let mut got: Vec<String> = Vec::new();
+    let mut style = TextStyle::new();
+    let mut styles: Vec<TextStyle> = Vec::new();
+    for c in input.chars() {
+        for event in m.write_char(c) {
+            if let Some(CsiCommand::SelectGraphicRendition(params)) = event.csi_command() {
+                for attr in SgrAttributes::new(params) {
+                    got.push(format!("{attr:?}"));
+                }
+                style.apply_params(params);
+                styles.push(style);
+            }
+        }
+    }
+    let want = std::vec![
+        "Bold",
+        "Underline(Curly)",
+        "Foreground(Rgb(255, 128, 0))",
+        "Background(Rgb(1, 2, 3))",
+        "UnderlineColor(Indexed(9))",
+        "Unknown([38, 5, 300])",
+        "Foreground(Ansi(11))",
+        "Unknown([4, 9])",
+        "Reset",
+    ];
+    assert_eq!(got, want);
+    // The second sequence has no parameters, and so resets everything.
+    let want_styles = [
+        TextStyle {
+            bold: true,
+            underline: Some(UnderlineStyle::Curly),
+            foreground: Some(SgrColor::Ansi(11)),
+            background: Some(SgrColor::Rgb(1, 2, 3)),
+            underline_color: Some(SgrColor::Indexed(9)),
+            ..TextStyle::new()
+        },
+        TextStyle::new(),
+    ];
+    assert_eq!(styles, want_styles);
+}
+
+#[test]
+fn esc_commands() {
+    use std::format;
+
+    let mut m = VtMachine::new();
+    let mut got: Vec<String> = Vec::new();
+    for c in "\x1b7\x1bc\x1bM\x1b=\x1b#8\x1b(B\x1b-A\x1b(%5\x1b 7".chars() {
+        for event in m.write_char(c) {
+            if let Some(cmd) = event.esc_command() {
+                got.push(format!("{cmd:?}"));
+            }
+        }
+    }
+    let want = std::vec![
+        "SaveCursor",
+        "FullReset",
+        "ReverseIndex",
+        "KeypadApplicationMode",
+        "AlignmentTest",
+        "DesignateCharset { slot: G0, size: Chars94, charset_intermediate: None, charset: 66 }",
+        "DesignateCharset { slot: G1, size: Chars96, charset_intermediate: None, charset: 65 }",
+        "DesignateCharset { slot: G0, size: Chars94, charset_intermediate: Some(37), charset: 53 }",
+        "Unknown { cmd: 55, intermediates: [32] }",
+    ];
+    assert_eq!(got, want);
+
+    // The same final character identifies different sets depending on the
+    // set size implied by the designator.
+    assert_eq!(
+        EscCommand::new(b'A', b")"),
+        EscCommand::DesignateCharset {
+            slot: CharsetSlot::G1,
+            size: CharsetSize::Chars94,
+            charset_intermediate: None,
+            charset: b'A',
+        }
+    );
+    assert_eq!(
+        EscCommand::new(b'A', b"-"),
+        EscCommand::DesignateCharset {
+            slot: CharsetSlot::G1,
+            size: CharsetSize::Chars96,
+            charset_intermediate: None,
+            charset: b'A',
+        }
+    );
+    assert_eq!(
+        EscCommand::new(b'A', b"/"),
+        EscCommand::DesignateCharset {
+            slot: CharsetSlot::G3,
+            size: CharsetSize::Chars96,
+            charset_intermediate: None,
+            charset: b'A',
+        }
+    );
+    // There is no 96-character designator for G0, and identifiers have at
+    // most one intermediate character.
+    assert_eq!(
+        EscCommand::new(b'A', b","),
+        EscCommand::Unknown {
+            cmd: b'A',
+            intermediates: b",",
+        }
+    );
+    assert_eq!(
+        EscCommand::new(b'5', b"(%%"),
+        EscCommand::Unknown {
+            cmd: b'5',
+            intermediates: b"(%%",
+        }
+    );
+}
+
+#[test]
+fn osc_commands() {
+    let tests: &[(&[u8], OscCommand<'static>)] = &[
+        (b"0;a;b", OscCommand::SetIconNameAndTitle(b"a;b")),
+        (
+            b"8;id=1;http://x/;y",
+            OscCommand::Hyperlink {
+                params: b"id=1",
+                uri: b"http://x/;y",
+            },
+        ),
+        (
+            b"8;;",
+            OscCommand::Hyperlink {
+                params: b"",
+                uri: b"",
+            },
+        ),
+        (
+            b"11;?",
+            OscCommand::DynamicColor {
+                target: DynamicColor::Background,
+                spec: OscColorSpec::Query,
+            },
+        ),
+        (
+            b"133;D;2",
+            OscCommand::ShellMark(ShellMark::CommandFinished { exit_code: Some(2) }),
+        ),
+        (b"133;A", OscCommand::ShellMark(ShellMark::PromptStart)),
+        (
+            b"9;hi",
+            OscCommand::Notify {
+                title: None,
+                body: b"hi",
+            },
+        ),
+        (
+            b"777;notify;t;b",
+            OscCommand::Notify {
+                title: Some(b"t"),
+                body: b"b",
+            },
+        ),
+        (
+            b"1337;CurrentDir=/",
+            OscCommand::ITerm2 {
+                key: b"CurrentDir",
+                value: b"/",
+            },
+        ),
+    ];
+    for (input, want) in tests {
+        assert_eq!(OscCommand::parse(input), *want, "for {input:?}");
+    }
+
+    let OscCommand::PaletteColors(pairs) = OscCommand::parse(b"4;1;rgb:ff/8/0000;2;?;3;#abc;4;red")
+    else {
+        panic!("not PaletteColors");
+    };
+    let got: Vec<_> = pairs.map(|(idx, spec)| (idx, spec.rgb())).collect();
+    assert_eq!(
+        got,
+        std::vec![
+            (1, Some((255, 136, 0))),
+            (2, None),
+            (3, Some((0xa0, 0xb0, 0xc0))),
+            (4, None),
+        ]
+    );
+
+    let OscCommand::Clipboard { selection, data } = OscCommand::parse(b"52;c;aGVsbG8=") else {
+        panic!("not Clipboard");
+    };
+    let ClipboardData::Set(data) = data else {
+        panic!("not Set");
+    };
+    assert_eq!(selection, b"c");
+    assert!(data.is_valid());
+    assert_eq!(data.bytes().collect::<Vec<u8>>(), b"hello");
+}
+
+#[test]
+fn osc_malformed() {
+    // Anything without a valid command number, or whose arguments are not
+    // in the form the command requires, is reported as unknown.
+    let tests: &[(&[u8], OscCommand<'static>)] = &[
+        (
+            b"",
+            OscCommand::Unknown {
+                number: None,
+                args: b"",
+            },
+        ),
+        (
+            b"x;1",
+            OscCommand::Unknown {
+                number: None,
+                args: b"x;1",
+            },
+        ),
+        (
+            b";2",
+            OscCommand::Unknown {
+                number: None,
+                args: b";2",
+            },
+        ),
+        (
+            b"65536;a",
+            OscCommand::Unknown {
+                number: None,
+                args: b"65536;a",
+            },
+        ),
+        (
+            b"104;1",
+            OscCommand::Unknown {
+                number: Some(104),
+                args: b"1",
+            },
+        ),
+        (
+            b"8;id=1",
+            OscCommand::Unknown {
+                number: Some(8),
+                args: b"id=1",
+            },
+        ),
+        (
+            b"52;c",
+            OscCommand::Unknown {
+                number: Some(52),
+                args: b"c",
+            },
+        ),
+        (
+            b"10;?;?",
+            OscCommand::Unknown {
+                number: Some(10),
+                args: b"?;?",
+            },
+        ),
+        (
+            b"9;4;1;50",
+            OscCommand::Unknown {
+                number: Some(9),
+                args: b"4;1;50",
+            },
+        ),
+        (
+            b"777;other;t;b",
+            OscCommand::Unknown {
+                number: Some(777),
+                args: b"other;t;b",
+            },
+        ),
+        (
+            b"777;notify",
+            OscCommand::Unknown {
+                number: Some(777),
+                args: b"notify",
+            },
+        ),
+        (
+            b"133;D;x",
+            OscCommand::ShellMark(ShellMark::CommandFinished { exit_code: None }),
+        ),
+        (b"133;Z;1", OscCommand::ShellMark(ShellMark::Other(b"Z;1"))),
+    ];
+    for (input, want) in tests {
+        assert_eq!(OscCommand::parse(input), *want, "for {input:?}");
+    }
+
+    // Palette pairs end at the first one that is not valid.
+    for input in [&b"4;1"[..], b"4;x;red;2;blue", b"4;;red"] {
+        let OscCommand::PaletteColors(pairs) = OscCommand::parse(input) else {
+            panic!("not PaletteColors for {input:?}");
+        };
+        assert_eq!(pairs.count(), 0, "for {input:?}");
+    }
+    let OscCommand::PaletteColors(pairs) = OscCommand::parse(b"4;1;red;x;blue;3;green") else {
+        panic!("not PaletteColors");
+    };
+    let got: Vec<_> = pairs.collect();
+    assert_eq!(got, [(1, OscColorSpec::Set(b"red"))]);
+    let OscCommand::PaletteColors(mut pairs) = OscCommand::parse(b"4;x;red;3;blue") else {
+        panic!("not PaletteColors");
+    };
+    assert_eq!(pairs.next(), None);
+    assert_eq!(pairs.next(), None);
+}
+
+#[test]
+fn osc_color_specs() {
+    fn rgb(spec: &[u8]) -> Option<(u8, u8, u8)> {
+        let mut input = b"10;".to_vec();
+        input.extend_from_slice(spec);
+        match OscCommand::parse(&input) {
+            OscCommand::DynamicColor { spec, .. } => spec.rgb(),
+            other => panic!("not DynamicColor: {other:?}"),
+        }
+    }
+
+    assert_eq!(rgb(b"rgb:f/f/f"), Some((255, 255, 255)));
+    assert_eq!(rgb(b"rgb:8/80/800"), Some((136, 128, 127)));
+    assert_eq!(rgb(b"rgb:FFFF/0/1"), Some((255, 0, 17)));
+    assert_eq!(rgb(b"#123456789abc"), Some((0x12, 0x56, 0x9a)));
+    assert_eq!(rgb(b"#102030"), Some((0x10, 0x20, 0x30)));
+
+    for bad in [
+        &b"?"[..],
+        b"red",
+        b"rgb:",
+        b"rgb:ff/80",
+        b"rgb:ff/80/00/11",
+        b"rgb:ff//00",
+        b"rgb:fffff/0/0",
+        b"rgb:gg/00/00",
+        b"rgbi:1/0/0",
+        b"#",
+        b"#12345",
+        b"#1234567890abc",
+        b"#12g",
+    ] {
+        assert_eq!(rgb(bad), None, "for {bad:?}");
+    }
+}
+
+#[test]
+fn osc_base64() {
+    fn base64(input: &[u8]) -> Base64Data<'_> {
+        match OscCommand::parse(input) {
+            OscCommand::Clipboard {
+                data: ClipboardData::Set(data),
+                ..
+            } => data,
+            other => panic!("not Clipboard: {other:?}"),
+        }
+    }
+
+    let valid: &[(&[u8], &[u8])] = &[
+        (b"52;c;", b""),
+        (b"52;c;YQ==", b"a"),
+        (b"52;c;YQ", b"a"),
+        (b"52;c;YWI=", b"ab"),
+        (b"52;c;YWJj", b"abc"),
+        (b"52;c;YWJjZA", b"abcd"),
+        (b"52;c;+/+/", &[0xfb, 0xff, 0xbf]),
+    ];
+    for (input, want) in valid {
+        let data = base64(input);
+        assert!(data.is_valid(), "for {input:?}");
+        assert_eq!(data.bytes().collect::<Vec<u8>>(), *want, "for {input:?}");
+    }
+
+    let invalid: &[&[u8]] = &[
+        b"52;c;a*b=",
+        b"52;c;Y",
+        b"52;c;YWJjZ",
+        b"52;c;YQ=",
+        b"52;c;YQ===",
+        b"52;c;YWI==",
+        b"52;c;YWJj=",
+        b"52;c;YQ==YQ==",
+        b"52;c;=",
+        b"52;c;YW I=",
+    ];
+    for input in invalid {
+        assert!(!base64(input).is_valid(), "for {input:?}");
+    }
+}
+
+#[test]
+fn dcs_commands() {
+    let none = VtParams::from_values(&[]);
+    let cmd = DcsCommand::new(b'q', None, none, b"$", b" q");
+    assert_eq!(cmd, DcsCommand::RequestStatus(b" q"));
+
+    let DcsCommand::RequestTermcap(names) =
+        DcsCommand::new(b'q', None, none, b"+", b"524742;436f;zz")
+    else {
+        panic!("not RequestTermcap");
+    };
+    let got: Vec<_> = names
+        .map(|name| (name.is_valid(), name.decodes_to(b"Co")))
+        .collect();
+    assert_eq!(got, std::vec![(true, false), (true, true), (false, false)]);
+    let DcsCommand::RequestTermcap(mut names) = DcsCommand::new(b'q', None, none, b"+", b"524742")
+    else {
+        panic!("not RequestTermcap");
+    };
+    assert_eq!(names.next().unwrap().bytes().collect::<Vec<u8>>(), b"RGB");
+    assert_eq!(names.next(), None);
+
+    let DcsCommand::UserDefinedKeys { clear, lock, keys } = DcsCommand::new(
+        b'|',
+        None,
+        VtParams::from_values(&[1]),
+        b"",
+        b"17/6869;18/;x/00",
+    ) else {
+        panic!("not UserDefinedKeys");
+    };
+    assert!(!clear);
+    assert!(lock);
+    let got: Vec<_> = keys.map(|(key, value)| (key, value.encoded())).collect();
+    assert_eq!(got, [(17, &b"6869"[..]), (18, b"")]);
+
+    let cmd = DcsCommand::new(b'q', None, none, b"", b"#0;2;0;0;0");
+    assert!(matches!(cmd, DcsCommand::Unknown { cmd: b'q', .. }));
+}
+
+#[test]
+fn dcs_malformed() {
+    let none = VtParams::from_values(&[]);
+
+    // Requests with parameters or a private marker are not the ones the
+    // decoder knows about.
+    let one = VtParams::from_values(&[1]);
+    let cmd = DcsCommand::new(b'q', None, one, b"$", b"m");
+    assert_eq!(
+        cmd,
+        DcsCommand::Unknown {
+            cmd: b'q',
+            private_marker: None,
+            params: one,
+            intermediates: b"$",
+            data: b"m",
+        }
+    );
+    let cmd = DcsCommand::new(b'q', None, one, b"+", b"436f");
+    assert!(matches!(cmd, DcsCommand::Unknown { cmd: b'q', .. }));
+    let cmd = DcsCommand::new(b'|', Some(b'?'), none, b"", b"17/41");
+    assert!(matches!(
+        cmd,
+        DcsCommand::Unknown {
+            private_marker: Some(b'?'),
+            ..
+        }
+    ));
+    let cmd = DcsCommand::new(b'|', None, none, b"!", b"17/41");
+    assert!(matches!(cmd, DcsCommand::Unknown { cmd: b'|', .. }));
+
+    // An empty XTGETTCAP has no names at all, while an empty name is
+    // still reported.
+    let DcsCommand::RequestTermcap(names) = DcsCommand::new(b'q', None, none, b"+", b"") else {
+        panic!("not RequestTermcap");
+    };
+    assert_eq!(names.count(), 0);
+    let DcsCommand::RequestTermcap(names) = DcsCommand::new(b'q', None, none, b"+", b";436f")
+    else {
+        panic!("not RequestTermcap");
+    };
+    let got: Vec<_> = names.map(|name| name.encoded()).collect();
+    assert_eq!(got, [&b""[..], b"436f"]);
+
+    // Hex data decodes up to the first invalid or unpaired digit.
+    let DcsCommand::RequestTermcap(names) =
+        DcsCommand::new(b'q', None, none, b"+", b"436F;6a;4;41g2;436f00;")
+    else {
+        panic!("not RequestTermcap");
+    };
+    let got: Vec<_> = names
+        .map(|name| {
+            (
+                name.is_valid(),
+                name.bytes().collect::<Vec<u8>>(),
+                name.decodes_to(b"Co"),
+            )
+        })
+        .collect();
+    let want = std::vec![
+        (true, b"Co".to_vec(), true),
+        (true, b"j".to_vec(), false),
+        (false, b"".to_vec(), false),
+        (false, b"A".to_vec(), false),
+        (true, b"Co\0".to_vec(), false),
+        (true, b"".to_vec(), false),
+    ];
+    assert_eq!(got, want);
+
+    // DECUDK definitions end at the first one that is not valid, and the
+    // flags default to clearing and locking.
+    let DcsCommand::UserDefinedKeys { clear, lock, keys } =
+        DcsCommand::new(b'|', None, none, b"", b"17/41;x/42;18/43")
+    else {
+        panic!("not UserDefinedKeys");
+    };
+    assert!(clear);
+    assert!(lock);
+    let got: Vec<_> = keys.map(|(key, value)| (key, value.encoded())).collect();
+    assert_eq!(got, [(17, &b"41"[..])]);
+    let DcsCommand::UserDefinedKeys { mut keys, .. } =
+        DcsCommand::new(b'|', None, none, b"", b"1/41;2;3/43")
+    else {
+        panic!("not UserDefinedKeys");
+    };
+    assert_eq!(keys.next().map(|(key, _)| key), Some(1));
+    assert_eq!(keys.next(), None);
+    assert_eq!(keys.next(), None);
+    for data in [&b""[..], b"17", b"/41", b"99999/41"] {
+        let DcsCommand::UserDefinedKeys { keys, .. } = DcsCommand::new(b'|', None, none, b"", data)
+        else {
+            panic!("not UserDefinedKeys");
+        };
+        assert_eq!(keys.count(), 0, "for {data:?}");
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn buffered_strings() {
+    use std::format;
+
+    fn describe(event: VtBufferedEvent<'_>) -> String {
+        match event {
+            VtBufferedEvent::Osc {
+                params,
+                terminator,
+                truncated,
+                ..
+            } => {
+                let params: Vec<_> = params.iter().map(|p| String::from_utf8_lossy(p)).collect();
+                format!("Osc {params:?} {terminator:#04x} truncated={truncated}")
+            }
+            VtBufferedEvent::Dcs {
+                header,
+                data,
+                terminator,
+                truncated,
+            } => {
+                let data = String::from_utf8_lossy(data);
+                format!(
+                    "Dcs {} {:?} {data:?} {terminator:#04x} truncated={truncated}",
+                    header.cmd as char, header.params
+                )
+            }
+            VtBufferedEvent::Event(event) => format!("{event:?}"),
+        }
+    }
+
+    let mut m = VtBufferedMachine::new(VtMachine::new());
+    let mut got: Vec<String> = Vec::new();
+    m.write_bytes(
+        "\x1b]0;héllo;world\x07a\x1bP1;2$qm\x1b\\\x1b]8;;".as_bytes(),
+        |event| got.push(describe(event)),
+    );
+    m.write_bytes_end(|event| got.push(describe(event)));
+    let want = std::vec![
+        "Osc [\"0\", \"héllo\", \"world\"] 0x07 truncated=false",
+        "Print(u8char('a'))",
+        "PrintEnd",
+        "Dcs q [1, 2] \"m\" 0x9c truncated=false",
+        "Osc [\"8\", \"\", \"\"] 0x04 truncated=false",
+    ];
+    assert_eq!(got, want);
+
+    let mut m = VtBufferedMachine::new(VtMachine::new()).with_max_len(4);
+    let mut got: Vec<String> = Vec::new();
+    m.write_bytes("\x1b]2;abcdef\x1b\\\x1bPqxyz12\x1b\\".as_bytes(), |event| {
+        got.push(describe(event))
+    });
+    let want = std::vec![
+        "Osc [\"2\", \"ab\"] 0x9c truncated=true",
+        "Dcs q [] \"xyz1\" 0x9c truncated=true",
+    ];
+    assert_eq!(got, want);
+
+    // A multi-byte character that doesn't fit ends the buffered content,
+    // even if later characters would fit in the remaining space.
+    let mut m = VtBufferedMachine::new(VtMachine::new()).with_max_len(4);
+    let mut got: Vec<(Vec<u8>, bool)> = Vec::new();
+    m.write_bytes("\x1bPqabcéd\x1b\\".as_bytes(), |event| {
+        if let VtBufferedEvent::Dcs {
+            data, truncated, ..
+        } = event
+        {
+            got.push((data.to_vec(), truncated));
+        }
+    });
+    assert_eq!(got, [(b"abc".to_vec(), true)]);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn sixel_decoder() {
+    let mut m = VtMachine::new();
+    let mut decoder: Option<SixelDecoder> = None;
+    let mut images: Vec<SixelImage> = Vec::new();
+    let input = "\x1bP0;1;0q\"1;1;4;4#1;2;100;0;0#1~~@$#2!2A\x1b\\";
+    for c in input.chars() {
+        for event in m.write_char(c) {
+            match event {
+                VtEvent::DcsStart { .. } => decoder = SixelDecoder::from_event(&event),
+                VtEvent::DcsChar(c) => decoder.as_mut().unwrap().put(c),
+                VtEvent::DcsEnd(_) => images.push(decoder.take().unwrap().finish()),
+                _ => {}
+            }
+        }
+    }
+    assert_eq!(images.len(), 1);
+    let image = &images[0];
+    assert_eq!((image.width(), image.height()), (4, 4));
+    assert!(image.transparent_background());
+
+    let red = [255, 0, 0, 255];
+    let dark_red = [204, 33, 33, 255];
+    let none = [0, 0, 0, 0];
+    let want: Vec<u8> = [
+        [red, red, red, none],
+        [dark_red, dark_red, none, none],
+        [red, red, none, none],
+        [red, red, none, none],
+    ]
+    .concat()
+    .concat();
+    assert_eq!(image.pixels(), want.as_slice());
+
+    // Sixel data can also arrive all at once, and any pixels beyond the
+    // maximum size are discarded.
+    let none = VtParams::from_values(&[]);
+    let mut decoder = SixelDecoder::new(none).with_max_size(3, 2);
+    decoder.put_bytes(b"#1;1;120;50;100!5~-~");
+    let image = decoder.finish();
+    assert_eq!((image.width(), image.height()), (3, 2));
+    assert_eq!(image.pixels(), [[255, 0, 0, 255]; 6].concat().as_slice());
+    assert!(!image.transparent_background());
+}
+
+#[test]
+fn owned_events() {
+    use std::format;
+
+    let mut m = VtMachine::new();
+    let mut owned: Vec<VtEventOwned> = Vec::new();
+    let mut borrowed: Vec<String> = Vec::new();
+    m.write_bytes(b"a\x1b[?1;2:3h\x1b(B\x1bPq#\x1b\\", |event| {
+        borrowed.push(format!("{event:?}"));
+        owned.push(event.into_owned());
+    });
+    let got: Vec<String> = owned
+        .iter()
+        .map(|event| format!("{:?}", event.as_event()))
+        .collect();
+    assert_eq!(got, borrowed);
+    assert_eq!(
+        format!("{:?}", owned[2]),
+        "DispatchCsi { cmd: 104, private_marker: Some(63), params: [1, 2:3], intermediates: [], truncated: false }",
+    );
+    assert_eq!(
+        format!("{:?}", owned[3].as_event().esc_command()),
+        "Some(DesignateCharset { slot: G0, size: Chars94, charset_intermediate: None, charset: 66 })",
+    );
+
+    // An owned event with less capacity than the machine keeps as many
+    // parameters as it can, and reports the rest as truncated.
+    let event = VtEvent::DispatchCsi {
+        cmd: b'm',
+        private_marker: None,
+        params: VtParams::from_values(&[1, 2, 3]),
+        intermediates: b"",
+        truncated: false,
+    };
+    let small = VtEventOwned::<2, 2>::from_event(event);
+    let VtEventOwned::DispatchCsi {
+        params, truncated, ..
+    } = small
+    else {
+        panic!("wrong event {small:?}");
+    };
+    assert_eq!(params.params(), VtParams::from_values(&[1, 2]));
+    assert!(truncated);
+
+    // The same applies to intermediate characters, including for escape
+    // sequences, so that a truncated sequence can't be mistaken for a
+    // different one.
+    let event = VtEvent::DispatchCsi {
+        cmd: b'p',
+        private_marker: None,
+        params: VtParams::from_values(&[1]),
+        intermediates: b" !",
+        truncated: false,
+    };
+    let small = VtEventOwned::<2, 1>::from_event(event);
+    let VtEventOwned::DispatchCsi {
+        params,
+        intermediates,
+        truncated,
+        ..
+    } = small
+    else {
+        panic!("wrong event {small:?}");
+    };
+    assert_eq!(params.params(), VtParams::from_values(&[1]));
+    assert_eq!(intermediates.chars(), b" ");
+    assert!(truncated);
+
+    let event = VtEvent::DispatchEsc {
+        cmd: b'5',
+        intermediates: b"(%",
+    };
+    let small = VtEventOwned::<2, 1>::from_event(event);
+    let VtEventOwned::DispatchEsc {
+        intermediates,
+        truncated,
+        ..
+    } = small
+    else {
+        panic!("wrong event {small:?}");
+    };
+    assert_eq!(intermediates.chars(), b"(");
+    assert!(truncated);
+    let VtEventOwned::DispatchEsc { truncated, .. } = event.into_owned() else {
+        panic!("wrong event");
+    };
+    assert!(!truncated);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn sixel_limits() {
+    let none = VtParams::from_values(&[]);
+    let decode = |max_size: (usize, usize), data: &[u8]| {
+        let mut decoder = SixelDecoder::new(none).with_max_size(max_size.0, max_size.1);
+        decoder.put_bytes(data);
+        decoder.finish()
+    };
+    let red = [255, 0, 0, 255];
+    let green = [0, 255, 0, 255];
+    let blue = [0, 0, 255, 255];
+
+    // A repeat count too large for its parameter saturates, and drawing
+    // stops at the maximum width rather than wrapping around.
+    let image = decode((10, 6), b"#1;2;100;0;0!99999999~~");
+    assert_eq!((image.width(), image.height()), (10, 6));
+    assert_eq!(image.pixels(), [red; 60].concat().as_slice());
+
+    // A repeat count of zero draws a single sixel.
+    let image = decode((10, 6), b"#1;2;0;100;0!0~");
+    assert_eq!((image.width(), image.height()), (1, 6));
+    assert_eq!(image.pixels(), [green; 6].concat().as_slice());
+
+    // Bands beyond the maximum height are discarded, and a band that
+    // crosses it is cropped.
+    let image = decode((2, 8), b"#1;2;0;0;100~-~-~");
+    assert_eq!((image.width(), image.height()), (1, 8));
+    assert_eq!(image.pixels(), [blue; 8].concat().as_slice());
+
+    // Raster attributes can't make the image larger than the maximum.
+    let image = decode((4, 3), b"\"1;1;100;100");
+    assert_eq!((image.width(), image.height()), (4, 3));
+    assert_eq!(image.pixels(), [0; 4 * 4 * 3].as_slice());
+
+    // Raster attributes alone are limited by the pixel budget, whether it
+    // is the default or a smaller one, and drawing can't go beyond it.
+    let mut decoder = SixelDecoder::new(none);
+    decoder.put_bytes(b"\"1;1;65535;65535");
+    let image = decoder.finish();
+    assert_eq!((image.width(), image.height()), (4096, 1024));
+    assert_eq!(image.pixels().len(), SixelDecoder::DEFAULT_MAX_PIXELS * 4);
+    let mut decoder = SixelDecoder::new(none).with_max_pixels(24);
+    decoder.put_bytes(b"\"1;1;65535;65535#1;2;100;0;0~");
+    let image = decoder.finish();
+    assert_eq!((image.width(), image.height()), (4096, 0));
+    assert!(image.pixels().is_empty());
+    let mut decoder = SixelDecoder::new(none).with_max_pixels(24);
+    decoder.put_bytes(b"#1;2;100;0;0~~~~~-~");
+    let image = decoder.finish();
+    assert_eq!((image.width(), image.height()), (4, 6));
+    assert_eq!(image.pixels(), [red; 24].concat().as_slice());
+    let mut decoder = SixelDecoder::new(none).with_max_pixels(36);
+    decoder.put_bytes(b"#1;2;100;0;0~~~-~");
+    let image = decoder.finish();
+    assert_eq!((image.width(), image.height()), (3, 12));
+    let blank = [0; 4];
+    let want: Vec<u8> = [[[red; 3]; 6].concat(), [[red, blank, blank]; 6].concat()]
+        .concat()
+        .concat();
+    assert_eq!(image.pixels(), want.as_slice());
+
+    // A color introducer with the wrong number of arguments is ignored,
+    // leaving the previous color selected, and palette indexes wrap.
+    let image = decode((10, 6), b"#1;2;100;0;0#2;2;0;100~#257;2;0;0;100#1~");
+    assert_eq!((image.width(), image.height()), (2, 6));
+    let want: Vec<u8> = [[red, blue]; 6].concat().concat();
+    assert_eq!(image.pixels(), want.as_slice());
+
+    // Characters with no meaning in sixel data are ignored.
+    let image = decode((10, 6), b"#1;2;100;0;0 \x01~\xff");
+    assert_eq!((image.width(), image.height()), (1, 6));
+    assert_eq!(image.pixels(), [red; 6].concat().as_slice());
+
+    // An image with no data at all is empty.
+    let image = decode((10, 6), b"");
+    assert_eq!((image.width(), image.height()), (0, 0));
+    assert!(image.pixels().is_empty());
+}
+
+fn collect_events<const P: usize, const I: usize>(
+    machine: &mut VtMachine<P, I>,
+    input: &str,
+) -> Vec<String> {
     use ::u8char::AsU8Chars;
     use std::format;
 
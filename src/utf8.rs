@@ -0,0 +1,123 @@
+use ::u8char::stream::U8CharStream;
+use u8char::u8char;
+
+/// Incremental UTF-8 decoding used by [`crate::VtMachine::write_bytes`].
+///
+/// The decoding itself is done by [`U8CharStream`]. This type only retains
+/// the bytes of an incomplete sequence between calls, so that a caller can
+/// pass in arbitrary chunks of a byte stream without aligning them with
+/// character boundaries first, and so that those bytes can be compared and
+/// included in a snapshot of the machine.
+#[derive(Clone, Copy)]
+pub(crate) struct Utf8Stream {
+    buf: [u8; 4],
+    len: u8,
+}
+
+impl Utf8Stream {
+    /// Constructs a new [`Utf8Stream`] with no pending bytes.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; 4],
+            len: 0,
+        }
+    }
+
+    /// Decodes a chunk of bytes, passing each resulting character to the
+    /// given callback.
+    ///
+    /// Invalid or interrupted sequences decode as U+FFFD REPLACEMENT
+    /// CHARACTER, and an incomplete sequence at the end of the chunk is
+    /// retained for the next call.
+    pub fn write(&mut self, bytes: &[u8], mut f: impl FnMut(u8char)) {
+        let mut stream = self.resume();
+        for b in bytes {
+            let had_pending = self.len != 0;
+            let mut count = 0;
+            for c in stream.more(core::slice::from_ref(b)) {
+                count += 1;
+                f(c);
+            }
+            // A byte that produces nothing has been retained as part of an
+            // incomplete sequence. A byte that interrupts an incomplete
+            // sequence produces a replacement character for it, and then
+            // either produces another character or starts a new sequence,
+            // which only a byte of 0xC0 or above can do.
+            match count {
+                0 if (self.len as usize) < self.buf.len() => {
+                    self.buf[self.len as usize] = *b;
+                    self.len += 1;
+                }
+                1 if had_pending && *b >= 0xc0 => {
+                    self.buf[0] = *b;
+                    self.len = 1;
+                }
+                _ => self.len = 0,
+            }
+        }
+    }
+
+    /// Discards any incomplete sequence, passing a replacement character to
+    /// the given callback to represent it if there was one.
+    pub fn end(&mut self, mut f: impl FnMut(u8char)) {
+        if self.len == 0 {
+            return;
+        }
+        let mut stream = self.resume();
+        self.len = 0;
+        for c in stream.end() {
+            f(c);
+        }
+    }
+
+    /// Discards any incomplete sequence without reporting it.
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the bytes of the incomplete sequence, if any.
+    #[inline(always)]
+    pub fn pending(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+
+    /// Replaces any incomplete sequence with the given bytes, as previously
+    /// returned by [`Self::pending`].
+    ///
+    /// Returns false, leaving no incomplete sequence, if the bytes are not
+    /// the start of a valid UTF-8 sequence.
+    pub fn restore(&mut self, pending: &[u8]) -> bool {
+        self.len = 0;
+        let mut valid = pending.len() < self.buf.len();
+        self.write(pending, |_| valid = false);
+        if !valid || self.pending() != pending {
+            self.len = 0;
+            return false;
+        }
+        true
+    }
+
+    /// Returns a decoder that has already consumed the pending bytes.
+    fn resume(&self) -> U8CharStream {
+        let mut stream = U8CharStream::new();
+        // The pending bytes are an incomplete sequence, so they produce no
+        // characters until more bytes arrive.
+        for _ in stream.more(self.pending()) {}
+        stream
+    }
+}
+
+impl PartialEq for Utf8Stream {
+    fn eq(&self, other: &Self) -> bool {
+        self.pending() == other.pending()
+    }
+}
+
+impl Eq for Utf8Stream {}
+
+impl core::fmt::Debug for Utf8Stream {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Utf8Stream").field(&self.pending()).finish()
+    }
+}